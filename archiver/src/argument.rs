@@ -7,12 +7,41 @@
 use once_cell::sync::OnceCell;
 use clap::{Parser, Subcommand};
 
+use crate::configure;
+
 #[derive(Debug)]
 pub struct Argument {
     pub command: ArchiverCommand,
     pub filesystem: Vec<String>,
     pub archive: String,
+    pub abort: bool,
+    pub purge: bool,
+    pub mappings: Vec<String>,
+    pub jobs: usize,
+    pub recursive: bool,
+    pub rollback: bool,
+    pub properties: bool,
+    pub overrides: Vec<String>,
+    pub excludes: Vec<String>,
+    pub transport: Option<String>,
+    pub compress: Option<String>,
+    pub recipient: Option<String>,
+    pub rate_limit: Option<String>,
+    pub buffer: Option<String>,
     pub progress: bool,
+    pub list: bool,
+    pub file: Option<String>,
+    pub snapshot: Option<String>,
+    pub to: Option<String>,
+    pub force: bool,
+    pub yes: bool,
+    pub parent: Option<String>,
+    pub writable: bool,
+    pub expire: i64,
+    pub cleanup: bool,
+    pub other: Option<String>,
+    // TODO: remove `allow` after the options are implemented.
+    #[allow(dead_code)]
     pub verbose: u8,
     pub dryrun: bool,
 }
@@ -53,10 +82,75 @@ pub enum ArchiverCommand {
         #[clap(required = true)]
         filesystem: Vec<String>,
 
-        /// The name of ZFS filesystem archiving original ZFS filesystems.
+        /// The name of ZFS filesystem archiving original ZFS filesystems,
+        /// or the absolute path of a directory storing the streams as files.
         #[arg(short, long, required = true)]
         archive: String,
 
+        /// The command wrapper running the commands on the archive, like 'ssh backup-host'.
+        #[arg(short, long)]
+        transport: Option<String>,
+
+        /// The rule naming the replications on the archive, can be repeated:
+        /// 'full' keeps the full name, 'strip:PREFIX' strips the prefix,
+        /// and 'SOURCE=TARGET' names the replication of SOURCE explicitly.
+        #[arg(short, long = "map", value_name = "RULE")]
+        mappings: Vec<String>,
+
+        /// The compressor of the stream files on a directory: zstd, gzip, xz, or none.
+        #[arg(long)]
+        compress: Option<String>,
+
+        /// The GnuPG recipient encrypting the stream files on a directory, or none.
+        #[arg(long)]
+        recipient: Option<String>,
+
+        /// The maximum bytes per second of the streams like '10M',
+        /// overriding 'elephant:ratelimit' on the archive.
+        #[arg(long)]
+        rate_limit: Option<String>,
+
+        /// The bytes of the buffer between 'zfs send' and the archive like '256M',
+        /// overriding 'elephant:buffer' on the archive.
+        #[arg(long)]
+        buffer: Option<String>,
+
+        /// The number of ZFS filesystems processed in parallel.
+        #[arg(short, long, default_value_t = configure::PARALLEL_JOBS)]
+        jobs: usize,
+
+        /// Abort the interrupted archives instead of resuming them.
+        #[arg(long, default_value_t = false)]
+        abort: bool,
+
+        /// Purge the snapshots on the archive with the retention policy of the archive after archiving,
+        /// the numbers of snapshots kept are overridden with 'elephant:keep-hours', 'elephant:keep-days',
+        /// 'elephant:keep-weeks', and 'elephant:keep-months' on the archive.
+        #[arg(long, default_value_t = false)]
+        purge: bool,
+
+        /// Roll back the diverged archives to the snapshots in common,
+        /// destroying the snapshots and the changes on the archives after them, not with --recursive.
+        #[arg(long, default_value_t = false)]
+        rollback: bool,
+
+        /// Archive the descendant filesystems with their properties.
+        #[arg(short, long, default_value_t = false)]
+        recursive: bool,
+
+        /// Archive the locally set properties of the filesystems.
+        #[arg(long, default_value_t = false)]
+        properties: bool,
+
+        /// Override a property on the archive like 'mountpoint=none', can be repeated.
+        #[arg(long = "set", value_name = "PROPERTY=VALUE")]
+        overrides: Vec<String>,
+
+        /// Exclude a property from the archive like 'compression', can be repeated.
+        /// 'mountpoint' is excluded unless it is overridden.
+        #[arg(long = "exclude", value_name = "PROPERTY")]
+        excludes: Vec<String>,
+
         /// Show the progress of archiving ZFS filesystems.
         #[arg(short, long, default_value_t = false)]
         progress: bool,
@@ -69,17 +163,158 @@ pub enum ArchiverCommand {
         #[arg(short, long, default_value_t = false)]
         dryrun: bool,
     },
-    /// TODO: implementation
+    /// Verify the archived snapshots and the stream files with their checksums.
+    Verify {
+        /// The names of one or more ZFS filesystems.
+        #[clap(required = true)]
+        filesystem: Vec<String>,
+
+        /// The name of ZFS filesystem archiving original ZFS filesystems,
+        /// or the absolute path of a directory storing the streams as files.
+        #[arg(short, long, required = true)]
+        archive: String,
+
+        /// The command wrapper running the commands on the archive, like 'ssh backup-host'.
+        #[arg(short, long)]
+        transport: Option<String>,
+
+        /// The rule naming the replications on the archive, can be repeated:
+        /// 'full' keeps the full name, 'strip:PREFIX' strips the prefix,
+        /// and 'SOURCE=TARGET' names the replication of SOURCE explicitly.
+        #[arg(short, long = "map", value_name = "RULE")]
+        mappings: Vec<String>,
+
+        /// The number of ZFS filesystems processed in parallel.
+        #[arg(short, long, default_value_t = configure::PARALLEL_JOBS)]
+        jobs: usize,
+
+        /// Print verbose information running on the program.
+        #[arg(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
+    },
+    /// Restore a snapshot of a ZFS filesystem from the archive,
+    /// or restore a file from a snapshot on this machine.
     Restore {
-        /// lists test values
+        /// The name of the original ZFS filesystem archived.
+        #[arg(required_unless_present = "file")]
+        filesystem: Option<String>,
+
+        /// The name of ZFS filesystem archiving original ZFS filesystems,
+        /// or the absolute path of a directory storing the streams as files.
+        #[arg(short, long, required_unless_present = "file")]
+        archive: Option<String>,
+
+        /// The path of a file or a directory in a mounted ZFS filesystem
+        /// restored from a snapshot of the filesystem instead of the archive.
+        #[arg(long, conflicts_with_all = ["filesystem", "archive"])]
+        file: Option<String>,
+
+        /// The command wrapper running the commands on the archive, like 'ssh backup-host'.
         #[arg(short, long)]
+        transport: Option<String>,
+
+        /// The rule naming the replications on the archive, can be repeated:
+        /// 'full' keeps the full name, 'strip:PREFIX' strips the prefix,
+        /// and 'SOURCE=TARGET' names the replication of SOURCE explicitly.
+        #[arg(short, long = "map", value_name = "RULE")]
+        mappings: Vec<String>,
+
+        /// List the snapshots restorable from the archive instead of restoring.
+        #[arg(short, long, default_value_t = false)]
         list: bool,
+
+        /// The snapshot restored, the newest snapshot by default.
+        /// Specify a short name, 'latest', 'oldest', a point in time like '2022-11-03 09:15', '2 days ago',
+        /// or 'last tuesday 09:00', or a point in time bounded with 'before' or 'after' like 'before yesterday'.
+        #[arg(short, long)]
+        snapshot: Option<String>,
+
+        /// The name of the ZFS filesystem restored to, the original filesystem by default.
+        /// The path of the file restored to, or the existing directory the file is restored into,
+        /// next to the original file by default.
+        #[arg(long)]
+        to: Option<String>,
+
+        /// Overwrite the existing ZFS filesystem or file restored to.
+        /// A file replaces only a file of the same type, and a directory is merged into the existing directory.
+        #[arg(short, long, default_value_t = false)]
+        force: bool,
+
+        /// Show the progress of restoring the ZFS filesystem.
+        #[arg(short, long, default_value_t = false)]
+        progress: bool,
+
+        /// Print verbose information running on the program.
+        #[arg(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
     },
-    /// TODO: implementation
+    /// Roll a ZFS filesystem back to a snapshot taken by Elephant Archive,
+    /// keeping a copy of the current data in case the rollback is undone.
+    Rollback {
+        /// The name of the ZFS filesystem rolled back.
+        filesystem: String,
+
+        /// The snapshot rolled back to, selected like the 'snapshot' option of the 'restore' subcommand.
+        snapshot: String,
+
+        /// Roll back without the confirmation.
+        #[arg(short, long, default_value_t = false)]
+        yes: bool,
+
+        /// Print verbose information running on the program.
+        #[arg(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
+
+        /// Show the snapshots and the clones destroyed without rolling back.
+        #[arg(short, long, default_value_t = false)]
+        dryrun: bool,
+    },
+    /// Clone a snapshot to a writable ZFS filesystem for inspection,
+    /// or destroy the expired clones.
+    Clone {
+        /// The snapshot cloned like 'pool/fs@2 days ago', selected like the 'snapshot' option
+        /// of the 'restore' subcommand after '@', or the newest snapshot of 'pool/fs'.
+        #[arg(required_unless_present = "cleanup")]
+        snapshot: Option<String>,
+
+        /// The ZFS filesystem the clones are created under, 'elephant-clones' on the pool by default.
+        #[arg(long)]
+        parent: Option<String>,
+
+        /// Mount the clone read-write instead of read-only.
+        #[arg(short, long, default_value_t = false)]
+        writable: bool,
+
+        /// The hours until the clone expires.
+        #[arg(short, long, default_value_t = configure::CLONE_EXPIRE_HOURS)]
+        expire: i64,
+
+        /// Destroy all of the expired clones instead of cloning.
+        #[arg(long, default_value_t = false, conflicts_with = "snapshot")]
+        cleanup: bool,
+
+        /// Print verbose information running on the program.
+        #[arg(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
+
+        /// Run the program under no changes.
+        #[arg(short, long, default_value_t = false)]
+        dryrun: bool,
+    },
+    /// Show the changes on the files of a ZFS filesystem after a snapshot.
     Diff {
-        /// lists test values
-        #[arg(short, long)]
-        list: bool,
+        /// The snapshot compared like 'pool/fs@2 days ago', selected like the 'snapshot' option
+        /// of the 'restore' subcommand after '@', or the newest snapshot of 'pool/fs'.
+        snapshot: String,
+
+        /// The later snapshot of the same filesystem compared with, selected like the first snapshot,
+        /// or 'live' to compare with the current filesystem.
+        #[arg(default_value = "live")]
+        other: String,
+
+        /// Print verbose information running on the program.
+        #[arg(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
     },
     /// Take a snapshot on a ZFS filesystem.
     Snapshot {
@@ -101,6 +336,21 @@ pub enum ArchiverCommand {
         #[clap(required = true)]
         filesystem: Vec<String>,
 
+        /// The name of ZFS filesystem archiving original ZFS filesystems,
+        /// purged with the retention policy of the archive overridden with its 'elephant:keep-*' properties.
+        #[arg(short, long)]
+        archive: Option<String>,
+
+        /// The command wrapper running the commands on the archive, like 'ssh backup-host'.
+        #[arg(short, long)]
+        transport: Option<String>,
+
+        /// The rule naming the replications on the archive, can be repeated:
+        /// 'full' keeps the full name, 'strip:PREFIX' strips the prefix,
+        /// and 'SOURCE=TARGET' names the replication of SOURCE explicitly.
+        #[arg(short, long = "map", value_name = "RULE")]
+        mappings: Vec<String>,
+
         /// Print verbose information running on the program. 
         #[arg(short, long, action = clap::ArgAction::Count)]
         verbose: u8,
@@ -115,6 +365,10 @@ pub enum ArchiverCommand {
         #[clap(required = true)]
         filesystem: Vec<String>,
 
+        /// Show only the snapshot selected like the 'snapshot' option of the 'restore' subcommand.
+        #[arg(short, long)]
+        snapshot: Option<String>,
+
         /// Print verbose information running on the program. 
         #[arg(short, long, action = clap::ArgAction::Count)]
         verbose: u8,
@@ -130,9 +384,7 @@ static SINGLETON_INSTANCE: OnceCell<Argument> = OnceCell::new();
 
 impl Argument {
     pub fn global() -> &'static Argument {
-        let singleton = SINGLETON_INSTANCE.get_or_init(|| Argument::new());
-
-        singleton
+        SINGLETON_INSTANCE.get_or_init(Argument::new)
     }
 
     pub fn new() -> Argument {
@@ -140,34 +392,176 @@ impl Argument {
 
         println!("{:?}", program);
 
-        let argument = match &program.command {
-            ArchiverCommand::Archive { filesystem, archive, progress,
+        let command = program.command.clone();
+        match &program.command {
+            ArchiverCommand::Archive { filesystem, archive, transport, mappings, jobs, compress, recipient,
+                    rate_limit, buffer, abort, purge, rollback, recursive, properties, overrides, excludes, progress,
                      verbose, dryrun } => {
                 Argument {
-                    command: program.command.clone(),
                     filesystem: filesystem.clone(),
                     archive: archive.clone(),
-                    progress: progress.clone(),
-                    verbose: verbose.clone(),
-                    dryrun: dryrun.clone(),
+                    abort: *abort,
+                    purge: *purge,
+                    rollback: *rollback,
+                    recursive: *recursive,
+                    properties: *properties,
+                    overrides: overrides.clone(),
+                    excludes: excludes.clone(),
+                    transport: transport.clone(),
+                    mappings: mappings.clone(),
+                    jobs: *jobs,
+                    compress: compress.clone(),
+                    recipient: recipient.clone(),
+                    rate_limit: rate_limit.clone(),
+                    buffer: buffer.clone(),
+                    progress: *progress,
+                    verbose: *verbose,
+                    dryrun: *dryrun,
+                    ..Argument::with(command)
                 }
             },
-            ArchiverCommand::Snapshot { filesystem, verbose, dryrun } |
-            ArchiverCommand::Purge { filesystem, verbose, dryrun } |
-            ArchiverCommand::Show { filesystem, verbose, dryrun }
+            ArchiverCommand::Verify { filesystem, archive, transport, mappings, jobs, verbose } => {
+                Argument {
+                    filesystem: filesystem.clone(),
+                    archive: archive.clone(),
+                    transport: transport.clone(),
+                    mappings: mappings.clone(),
+                    jobs: *jobs,
+                    verbose: *verbose,
+                    ..Argument::with(command)
+                }
+            },
+            ArchiverCommand::Restore { filesystem, archive, file, transport, mappings, list, snapshot, to, force,
+                    progress, verbose } => {
+                Argument {
+                    filesystem: filesystem.iter().cloned().collect(),
+                    archive: archive.clone().unwrap_or_default(),
+                    file: file.clone(),
+                    transport: transport.clone(),
+                    mappings: mappings.clone(),
+                    list: *list,
+                    snapshot: snapshot.clone(),
+                    to: to.clone(),
+                    force: *force,
+                    progress: *progress,
+                    verbose: *verbose,
+                    ..Argument::with(command)
+                }
+            },
+            ArchiverCommand::Purge { filesystem, archive, transport, mappings, verbose, dryrun } => {
+                Argument {
+                    filesystem: filesystem.clone(),
+                    archive: archive.clone().unwrap_or_default(),
+                    transport: transport.clone(),
+                    mappings: mappings.clone(),
+                    verbose: *verbose,
+                    dryrun: *dryrun,
+                    ..Argument::with(command)
+                }
+            },
+            ArchiverCommand::Rollback { filesystem, snapshot, yes, verbose, dryrun } => {
+                Argument {
+                    filesystem: vec![filesystem.clone()],
+                    snapshot: Some(snapshot.clone()),
+                    yes: *yes,
+                    verbose: *verbose,
+                    dryrun: *dryrun,
+                    ..Argument::with(command)
+                }
+            },
+            ArchiverCommand::Clone { snapshot, parent, writable, expire, cleanup, verbose, dryrun } => {
+                let (filesystem, snapshot) = match snapshot {
+                    Some(snapshot) => Self::split_snapshot(snapshot),
+                    None => (Vec::new(), None),
+                };
+                Argument {
+                    filesystem,
+                    snapshot,
+                    parent: parent.clone(),
+                    writable: *writable,
+                    expire: *expire,
+                    cleanup: *cleanup,
+                    verbose: *verbose,
+                    dryrun: *dryrun,
+                    ..Argument::with(command)
+                }
+            },
+            ArchiverCommand::Snapshot { filesystem, verbose, dryrun }
+            => {
+                Argument {
+                    filesystem: filesystem.clone(),
+                    verbose: *verbose,
+                    dryrun: *dryrun,
+                    ..Argument::with(command)
+                }
+            },
+            ArchiverCommand::Show { filesystem, snapshot, verbose, dryrun }
             => {
                 Argument {
-                    command: program.command.clone(),
                     filesystem: filesystem.clone(),
-                    archive: "Not expected".to_string(),
-                    progress: false,
-                    verbose: verbose.clone(),
-                    dryrun: dryrun.clone(),
+                    snapshot: snapshot.clone(),
+                    verbose: *verbose,
+                    dryrun: *dryrun,
+                    ..Argument::with(command)
+                }
+            },
+            ArchiverCommand::Diff { snapshot, other, verbose } => {
+                let (filesystem, snapshot) = Self::split_snapshot(snapshot);
+                Argument {
+                    filesystem,
+                    snapshot,
+                    // the live filesystem is compared without the other snapshot.
+                    other: Some(other.clone()).filter(|o| o != "live"),
+                    verbose: *verbose,
+                    ..Argument::with(command)
                 }
             },
-            _ => {panic!("not implemented yet.")}
-        };
+        }
+    }
+
+    /// Split `snapshot` like `pool/fs@2 days ago` into the filesystem and the selector of its snapshot.
+    /// The selector is `None` without '@'.
+    fn split_snapshot(snapshot: &str) -> (Vec<String>, Option<String>) {
+        match snapshot.split_once('@') {
+            Some((filesystem, selector)) => (vec![filesystem.to_string()], Some(selector.to_string())),
+            None => (vec![snapshot.to_string()], None),
+        }
+    }
 
-        argument
+    /// Make an argument instance of `command` with the default values.
+    fn with(command: ArchiverCommand) -> Argument {
+        Argument {
+            command,
+            filesystem: Vec::new(),
+            archive: String::new(),
+            abort: false,
+            purge: false,
+            mappings: Vec::new(),
+            jobs: configure::PARALLEL_JOBS,
+            rollback: false,
+            recursive: false,
+            properties: false,
+            overrides: Vec::new(),
+            excludes: Vec::new(),
+            transport: None,
+            compress: None,
+            recipient: None,
+            rate_limit: None,
+            buffer: None,
+            progress: false,
+            list: false,
+            file: None,
+            snapshot: None,
+            to: None,
+            force: false,
+            yes: false,
+            parent: None,
+            writable: false,
+            expire: configure::CLONE_EXPIRE_HOURS,
+            cleanup: false,
+            other: None,
+            verbose: 0,
+            dryrun: false,
+        }
     }
 }
@@ -13,3 +13,34 @@ pub const SNAPSHOT_KEEP_DAYS: i32 = 30;
 
 /// Number of hourly snapshots keeping
 pub const SNAPSHOT_KEEP_HOURS: i32 = 24;
+
+/// Number of monthly snapshots keeping on the archive
+pub const ARCHIVE_KEEP_MONTHS: i32 = 120;
+
+/// Number of weekly snapshots keeping on the archive
+pub const ARCHIVE_KEEP_WEEKS: i32 = 52;
+
+/// Number of daily snapshots keeping on the archive
+pub const ARCHIVE_KEEP_DAYS: i32 = 30;
+
+/// Number of hourly snapshots keeping on the archive
+pub const ARCHIVE_KEEP_HOURS: i32 = 24;
+
+/// Properties excluded on receiving the properties of a filesystem,
+/// the archived filesystems never mount over the original ones
+pub const RECEIVE_EXCLUDE_PROPERTIES: [&str; 1] = ["mountpoint"];
+
+/// Number of ZFS filesystems processed in parallel by default
+pub const PARALLEL_JOBS: usize = 1;
+
+/// Compressor of the stream files on a new stream store
+pub const STREAM_COMPRESSION: &str = "zstd";
+
+/// Interval seconds of logging the progress on a non-terminal output
+pub const PROGRESS_LOG_SECONDS: u64 = 10;
+
+/// Filesystem under the pool of a snapshot the clones for inspection are created under
+pub const CLONE_PARENT: &str = "elephant-clones";
+
+/// Number of hours a clone for inspection is kept until it is cleaned up
+pub const CLONE_EXPIRE_HOURS: i64 = 24;
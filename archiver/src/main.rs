@@ -4,6 +4,8 @@
 
 mod configure;
 mod argument;
+mod store;
+mod recovery;
 mod subcommand;
 mod zfs;
 
@@ -0,0 +1,85 @@
+// Copyright (c) 2022 Patineboot.
+// All rights reserved.
+// Elephant Archive is licensed under BSD 2-Clause License.
+
+use std::fs::{self, File, FileTimes, FileType, Metadata};
+use std::io;
+use std::os::unix::fs::{self as unix_fs, MetadataExt};
+use std::path::Path;
+
+/// Copy `source` to `destination` with its ownership, permissions and timestamps.
+/// A directory is copied with all of its contents, and a symbolic link is copied as a link.
+/// The existing entries are replaced only if `overwrite` is true and only by the entries of the same type,
+/// a directory is copied into the existing directory without removing the other entries in it.
+/// Return the number of files copied.
+pub fn copy_preserving(source: &Path, destination: &Path, overwrite: bool) -> Result<u64, String> {
+    let failed = |e: io::Error| format!("Failed to copy '{}' to '{}': {e}", source.display(), destination.display());
+
+    let metadata = fs::symlink_metadata(source).map_err(failed)?;
+    let file_type = metadata.file_type();
+    let exists = existing(destination, file_type, overwrite)?;
+
+    if file_type.is_symlink() {
+        let link = fs::read_link(source).map_err(failed)?;
+        if exists {
+            fs::remove_file(destination).map_err(failed)?;
+        }
+        unix_fs::symlink(&link, destination).map_err(failed)?;
+        unix_fs::lchown(destination, Some(metadata.uid()), Some(metadata.gid())).map_err(failed)?;
+        return Ok(1);
+    }
+
+    let mut count = 0;
+    if file_type.is_dir() {
+        if !exists {
+            fs::create_dir(destination).map_err(failed)?;
+        }
+        for entry in fs::read_dir(source).map_err(failed)? {
+            let entry = entry.map_err(failed)?;
+            count += copy_preserving(&entry.path(), &destination.join(entry.file_name()), overwrite)?;
+        }
+    }
+    else if file_type.is_file() {
+        fs::copy(source, destination).map_err(failed)?;
+        count = 1;
+    }
+    else {
+        elephant_log::warn!("skip the special file: {}", source.display());
+        return Ok(0);
+    }
+
+    // the timestamps of a directory are set after its contents are copied.
+    preserve(destination, &metadata).map_err(failed)?;
+
+    Ok(count)
+}
+
+/// Confirm `destination` can be a copy of the `file_type` entry, and return whether it exists.
+/// The existing entry is replaced only if `overwrite` is true and it has the same type.
+fn existing(destination: &Path, file_type: FileType, overwrite: bool) -> Result<bool, String> {
+    let metadata = match fs::symlink_metadata(destination) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(false),
+    };
+
+    if !overwrite {
+        return Err(format!("'{}' exists", destination.display()));
+    }
+    if metadata.file_type() != file_type {
+        return Err(format!("'{}' exists with another type, it is not replaced", destination.display()));
+    }
+
+    Ok(true)
+}
+
+/// Set the ownership, the permissions and the timestamps of `metadata` on `path`.
+fn preserve(path: &Path, metadata: &Metadata) -> io::Result<()> {
+    unix_fs::chown(path, Some(metadata.uid()), Some(metadata.gid()))?;
+    // the permissions are set after the ownership, changing the owner clears the setuid bit.
+    fs::set_permissions(path, metadata.permissions())?;
+
+    let times = FileTimes::new()
+            .set_accessed(metadata.accessed()?)
+            .set_modified(metadata.modified()?);
+    File::open(path)?.set_times(times)
+}
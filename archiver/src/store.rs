@@ -0,0 +1,722 @@
+// Copyright (c) 2022 Patineboot.
+// All rights reserved.
+// Elephant Archive is licensed under BSD 2-Clause License.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Mutex, PoisonError};
+use sha2::{Sha256, Digest};
+
+use crate::configure;
+use crate::zfs::{Filesystem, Snapshot, Relay, SendOption, Plan};
+use crate::zfs::filesystem::HOLD_TAG_PREFIX;
+
+/// The name of the manifest file on a stream store.
+const MANIFEST_FILE: &str = "manifest";
+
+/// The name of the settings file on a stream store.
+const SETTINGS_FILE: &str = "settings";
+
+/// The value of the settings meaning nothing is applied.
+const NONE: &str = "none";
+
+/// The compressors: the name, the command line compressing, the command line decompressing, and the extension.
+const COMPRESSORS: [(&str, &str, &str, &str); 3] = [
+    ("zstd", "zstd -c -q", "zstd -d -c -q", "zst"),
+    ("gzip", "gzip -c", "gzip -d -c", "gz"),
+    ("xz", "xz -c", "xz -d -c", "xz"),
+];
+
+/// The scheme of the encryption with the public key of GnuPG.
+const GPG_SCHEME: &str = "gpg:";
+
+/// Command Line: encrypt with the public key of a GnuPG recipient.
+const GPG_ENCRYPT: &str = "gpg --batch --quiet --trust-model always --encrypt --recipient";
+
+/// Command Line: decrypt with the secret key of GnuPG.
+const GPG_DECRYPT: &str = "gpg --batch --quiet --decrypt";
+
+/// Split `line` into tab-separated `key=value` fields.
+fn fields(line: &str) -> Vec<(&str, &str)> {
+    line.split('\t').filter_map(|field| field.split_once('=')).collect()
+}
+
+/// The compression and the encryption applied to the streams on a stream store.
+/// The compression is the name of a compressor or `none`, and the encryption is
+/// `gpg:<recipient>` or `none`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamCodec {
+    pub compression: String,
+    pub encryption: String,
+}
+
+impl StreamCodec {
+    /// Make a codec, and confirm the compressor and the encryption are supported.
+    pub fn new(compression: &str, encryption: &str) -> Result<StreamCodec, String> {
+        let codec = StreamCodec {
+            compression: compression.to_string(),
+            encryption: encryption.to_string(),
+        };
+
+        if codec.compressor().is_none() && compression != NONE {
+            return Err(format!("'{compression}' compression is not supported."));
+        }
+        if !encryption.starts_with(GPG_SCHEME) && encryption != NONE {
+            return Err(format!("'{encryption}' encryption is not supported."));
+        }
+
+        Ok(codec)
+    }
+
+    fn compressor(&self) -> Option<&'static (&'static str, &'static str, &'static str, &'static str)> {
+        COMPRESSORS.iter().find(|c| c.0 == self.compression)
+    }
+
+    fn recipient(&self) -> Option<&str> {
+        self.encryption.strip_prefix(GPG_SCHEME)
+    }
+
+    /// Get the command lines compressing and encrypting a stream in order.
+    pub fn encoders(&self) -> Vec<String> {
+        let mut encoders = Vec::new();
+        if let Some((_, compressor, _, _)) = self.compressor() {
+            encoders.push(compressor.to_string());
+        }
+        if let Some(recipient) = self.recipient() {
+            encoders.push(format!("{GPG_ENCRYPT} {recipient}"));
+        }
+
+        encoders
+    }
+
+    /// Get the command lines decrypting and decompressing a stream in order,
+    /// the reverse of the encoders.
+    pub fn decoders(&self) -> Vec<String> {
+        let mut decoders = Vec::new();
+        if self.recipient().is_some() {
+            decoders.push(GPG_DECRYPT.to_string());
+        }
+        if let Some((_, _, decompressor, _)) = self.compressor() {
+            decoders.push(decompressor.to_string());
+        }
+
+        decoders
+    }
+
+    /// Get the extension of the stream files following `zfs`, like `zfs.zst.gpg`.
+    pub fn extension(&self) -> String {
+        let mut extension = STREAM_EXTENSION.to_string();
+        if let Some((_, _, _, compressed)) = self.compressor() {
+            extension = format!("{extension}.{compressed}");
+        }
+        if self.recipient().is_some() {
+            extension = format!("{extension}.gpg");
+        }
+
+        extension
+    }
+}
+
+/// The extension of the stream files on a stream store.
+const STREAM_EXTENSION: &str = "zfs";
+
+/// A stream written on a stream store.
+/// A stream is a line on the manifest file with tab-separated `key=value` fields,
+/// the unknown fields are ignored and the missing fields are empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamEntry {
+    /// The name of the ZFS filesystem sent.
+    pub dataset: String,
+    /// The short name of the snapshot sent.
+    pub snapshot: String,
+    /// The GUID of the snapshot sent.
+    pub guid: String,
+    /// The short name of the base snapshot of an incremental stream, or `None` for a full stream.
+    pub base: Option<String>,
+    /// The number of bytes of the stream.
+    pub bytes: u64,
+    /// The path of the stream file relative to the stream store.
+    pub file: String,
+    /// The compression and the encryption applied to the stream file.
+    pub codec: StreamCodec,
+    /// The SHA-256 checksum of the stream file, or empty if it was not recorded.
+    pub sha256: String,
+}
+
+impl StreamEntry {
+    fn parse(line: &str) -> StreamEntry {
+        let mut entry = StreamEntry {
+            dataset: String::new(),
+            snapshot: String::new(),
+            guid: String::new(),
+            base: None,
+            bytes: 0,
+            file: String::new(),
+            // the streams written before the codec was recorded are not compressed.
+            codec: StreamCodec {
+                compression: NONE.to_string(),
+                encryption: NONE.to_string(),
+            },
+            sha256: String::new(),
+        };
+
+        for (key, value) in fields(line) {
+            match key {
+                "dataset" => entry.dataset = value.to_string(),
+                "snapshot" => entry.snapshot = value.to_string(),
+                "guid" => entry.guid = value.to_string(),
+                "base" if value != "-" => entry.base = Some(value.to_string()),
+                "bytes" => entry.bytes = value.parse().unwrap_or(0),
+                "file" => entry.file = value.to_string(),
+                "compression" => entry.codec.compression = value.to_string(),
+                "encryption" => entry.codec.encryption = value.to_string(),
+                "sha256" => entry.sha256 = value.to_string(),
+                _ => (),
+            }
+        }
+
+        entry
+    }
+
+    fn format(&self) -> String {
+        let base = self.base.as_deref().unwrap_or("-");
+        let fields = [
+            format!("dataset={}", self.dataset),
+            format!("snapshot={}", self.snapshot),
+            format!("guid={}", self.guid),
+            format!("base={base}"),
+            format!("bytes={}", self.bytes),
+            format!("file={}", self.file),
+            format!("compression={}", self.codec.compression),
+            format!("encryption={}", self.codec.encryption),
+            format!("sha256={}", self.sha256),
+        ];
+
+        fields.join("\t")
+    }
+
+    /// Get the name of the snapshot sent with the stream.
+    pub fn snapshot_name(&self) -> String {
+        format!("{}@{}", self.dataset, self.snapshot)
+    }
+}
+
+/// A directory storing the streams of `zfs send` as files with a manifest file.
+/// A stream store is the archive of the filesystems on a destination without ZFS.
+#[derive(Debug)]
+pub struct StreamStore {
+    directory: PathBuf,
+    codec: StreamCodec,
+    settings: Vec<(String, String)>,
+    /// The streams on the manifest file, locked only while a stream is recorded
+    /// to write the streams of the filesystems in parallel.
+    entries: Mutex<Vec<StreamEntry>>,
+}
+
+impl StreamStore {
+    /// Confirm `archive` is the path of a stream store or the name of a ZFS filesystem.
+    /// A stream store is specified with an absolute path.
+    pub fn is_store(archive: &str) -> bool {
+        archive.starts_with('/')
+    }
+
+    /// Open the stream store on the `directory` directory.
+    pub fn open(directory: &str) -> Result<StreamStore, String> {
+        let directory = PathBuf::from(directory);
+        if !directory.is_dir() {
+            let message = format!("'{}' directory is not found.", directory.display());
+            elephant_log::error!("{message}");
+            return Err(message);
+        }
+
+        let manifest = directory.join(MANIFEST_FILE);
+        let entries = if manifest.exists() {
+            let content = fs::read_to_string(&manifest)
+                    .map_err(|e| format!("Failed to read '{}': {e}", manifest.display()))?;
+            content.lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(StreamEntry::parse)
+                    .collect()
+        }
+        else {
+            Vec::new()
+        };
+
+        let settings_file = directory.join(SETTINGS_FILE);
+        let (codec, settings) = if settings_file.exists() {
+            let content = fs::read_to_string(&settings_file)
+                    .map_err(|e| format!("Failed to read '{}': {e}", settings_file.display()))?;
+            let settings: Vec<(String, String)> = content.lines().flat_map(fields)
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect();
+
+            let find = |name: &str| settings.iter()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.as_str());
+            let compression = find("compression").unwrap_or(configure::STREAM_COMPRESSION);
+            let encryption = find("encryption").unwrap_or(NONE);
+            (StreamCodec::new(compression, encryption)?, settings)
+        }
+        else {
+            (StreamCodec::new(configure::STREAM_COMPRESSION, NONE)?, Vec::new())
+        };
+
+        Ok(StreamStore { directory, codec, settings, entries: Mutex::new(entries) })
+    }
+
+    /// Get the value of the `key` setting of this stream store.
+    /// The settings are the lines of `key=value` on the settings file.
+    pub fn setting(&self, key: &str) -> Option<String> {
+        self.settings.iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value.clone())
+    }
+
+    /// Change the compression and the encryption of the streams written after now,
+    /// and save them as the settings of this stream store.
+    /// The streams written before keep their compression and encryption on the manifest file.
+    /// # Arguments
+    /// - `compression` - The name of a compressor or `none`, or `None` to keep the current one.
+    /// - `recipient` - The GnuPG recipient encrypting the streams or `none`,
+    ///   or `None` to keep the current one.
+    pub fn configure(&mut self, compression: Option<&str>, recipient: Option<&str>) -> Result<(), String> {
+        let compression = compression.unwrap_or(&self.codec.compression).to_string();
+        let encryption = match recipient {
+            Some(NONE) => NONE.to_string(),
+            Some(recipient) => format!("{GPG_SCHEME}{recipient}"),
+            None => self.codec.encryption.clone(),
+        };
+        self.codec = StreamCodec::new(&compression, &encryption)?;
+
+        // keep the other settings as they are.
+        self.settings.retain(|(key, _)| key != "compression" && key != "encryption");
+        self.settings.insert(0, ("encryption".to_string(), encryption));
+        self.settings.insert(0, ("compression".to_string(), compression));
+
+        let settings_file = self.directory.join(SETTINGS_FILE);
+        let content: String = self.settings.iter()
+                .map(|(key, value)| format!("{key}={value}\n"))
+                .collect();
+        fs::write(&settings_file, content)
+                .map_err(|e| format!("Failed to write '{}': {e}", settings_file.display()))?;
+
+        elephant_log::info!("'{}' stream store is configured with {:?}", self.directory.display(), self.codec);
+        Ok(())
+    }
+
+    /// Get the streams of the `dataset` filesystem in the order they were written.
+    pub fn entries(&self, dataset: &str) -> Vec<StreamEntry> {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries.iter()
+                .filter(|e| e.dataset == dataset)
+                .cloned().collect()
+    }
+
+    /// Get the snapshot instances of the `dataset` filesystem stored in this stream store.
+    pub fn snapshots(&self, dataset: &str) -> Vec<Snapshot> {
+        let entries = self.entries(dataset);
+        entries.iter().map(|e| Snapshot::new(&e.snapshot_name())).collect()
+    }
+
+    /// Archive a ZFS filesystem to this stream store.
+    /// The whole of the newest snapshot is written at the first time, and after that
+    /// every snapshot taken after the newest common snapshot is written as an incremental stream.
+    /// Return the snapshot instance of the newest stream.
+    pub fn archive(&self, original: &Filesystem, option: &SendOption) -> Result<Snapshot, String> {
+        let snapshot = match original.snapshots().last() {
+            Some(snapshot) => snapshot.clone(),
+            None => {
+                let message = format!("'{}' filesystem has no snapshot to archive.", original.name());
+                elephant_log::error!("{message}");
+                return Err(message);
+            },
+        };
+
+        let sendings = self.sendings(original, &snapshot, option)?;
+        if sendings.is_empty() {
+            elephant_log::info!("'{}' stream store already has {}", self.directory.display(), snapshot.name());
+        }
+
+        for (base, sending) in sendings {
+            self.write(original, base.as_ref(), &sending, option)?;
+        }
+
+        // the newest snapshot written is the base of the next incremental stream.
+        original.keep_base(&snapshot, &self.hold_tag(), option.recursive)?;
+
+        Ok(snapshot)
+    }
+
+    /// Get the tag holding the base snapshots of the streams written to this stream store.
+    /// The tag is named after the hash of the directory, whose path may have spaces splitting a command line.
+    fn hold_tag(&self) -> String {
+        let digest = Sha256::digest(self.directory.to_string_lossy().as_bytes());
+        let hash: String = digest.iter().take(8).map(|b| format!("{b:02x}")).collect();
+
+        format!("{HOLD_TAG_PREFIX}store-{hash}")
+    }
+
+    /// Get the streams of `original` written to archive `snapshot` as pairs of a base and a snapshot.
+    /// Every snapshot taken after the newest common snapshot is written as an incremental stream.
+    fn sendings(&self, original: &Filesystem, snapshot: &Snapshot, option: &SendOption)
+            -> Result<Vec<(Option<Snapshot>, Snapshot)>, String> {
+        let stored = self.snapshots(&original.name());
+        let sendings = if stored.is_empty() {
+            vec![(None, snapshot.clone())]
+        }
+        else {
+            // a replication stream of the descendant filesystems cannot start from a bookmark.
+            let base = if option.recursive {
+                original.newest_common(&stored)
+            }
+            else {
+                original.newest_base(&stored)
+            };
+            let base = match base {
+                Some(base) => base,
+                None => {
+                    let message = format!("'{}' stream store has no snapshot in common with '{}'.",
+                            self.directory.display(), original.name());
+                    elephant_log::error!("{message}");
+                    return Err(message);
+                },
+            };
+
+            // every snapshot is written as a stream to restore it individually.
+            let mut sendings = Vec::new();
+            let mut previous = base;
+            for newer in original.newer_than(&previous) {
+                sendings.push((Some(previous), newer.clone()));
+                previous = newer;
+            }
+            sendings
+        };
+
+        Ok(sendings)
+    }
+
+    /// Make the plan archiving `original` to this stream store without archiving it.
+    /// The plan shows the snapshots written with their estimated bytes and the space available.
+    pub fn plan(&self, original: &Filesystem, option: &SendOption) -> Result<Plan, String> {
+        let snapshot = match original.snapshots().last() {
+            Some(snapshot) => snapshot.clone(),
+            None => return Err(format!("'{}' filesystem has no snapshot to archive.", original.name())),
+        };
+
+        let sendings = self.sendings(original, &snapshot, option)?;
+        let kind = match sendings.first() {
+            None => format!("up to date with {}", snapshot.short_name()),
+            Some((None, _)) => "full".to_string(),
+            Some((Some(base), _)) => format!("incremental from {}", base.name()),
+        };
+
+        let mut streams = Vec::new();
+        for (base, sending) in sendings.iter() {
+            let mut estimated = original.estimate(sending, base.as_ref(), option)?;
+            streams.append(&mut estimated);
+        }
+
+        Ok(Plan {
+            source: original.name(),
+            target: self.directory.display().to_string(),
+            kind,
+            streams,
+            available: self.available(),
+            notes: Vec::new(),
+        })
+    }
+
+    /// Get the bytes available on the file system of this stream store, or `None` if unknown.
+    fn available(&self) -> Option<u64> {
+        let output = Command::new("df").arg("-P").arg("-k").arg(&self.directory).output().ok()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        // the 4th field on the 2nd line is the available space in kilobytes.
+        let line = stdout.lines().nth(1)?;
+        let kilobytes: u64 = line.split_whitespace().nth(3)?.parse().ok()?;
+        Some(kilobytes * 1024)
+    }
+
+    /// Write a stream of `snapshot` to a stream file and record it on the manifest file.
+    fn write(&self, original: &Filesystem, base: Option<&Snapshot>, snapshot: &Snapshot,
+            option: &SendOption) -> Result<(), String> {
+        let file = format!("{}/{}.{}", original.name(), snapshot.short_name(), self.codec.extension());
+        let path = self.directory.join(&file);
+        let partial = self.directory.join(format!("{file}.part"));
+
+        elephant_log::info!("archive: {} to {}", snapshot.name(), path.display());
+        let parent = path.parent().unwrap();
+        fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create '{}': {e}", parent.display()))?;
+
+        // the stream file appears after the whole of the stream is written.
+        let transfer = original.send_file(snapshot, base, option, &self.codec.encoders(), &partial)?;
+        fs::rename(&partial, &path)
+                .map_err(|e| format!("Failed to rename '{}': {e}", partial.display()))?;
+
+        let entry = StreamEntry {
+            dataset: original.name(),
+            snapshot: snapshot.short_name(),
+            guid: original.guid(snapshot),
+            base: base.map(|b| b.short_name()),
+            bytes: transfer.bytes,
+            file,
+            codec: self.codec.clone(),
+            sha256: transfer.sha256,
+        };
+        self.record(entry)
+    }
+
+    /// Confirm the stream files of the `dataset` filesystem are intact with their checksums.
+    /// Return the problems found on the stream files.
+    pub fn verify(&self, dataset: &str) -> Vec<String> {
+        self.entries(dataset).iter().filter_map(|entry| self.check(entry)).collect()
+    }
+
+    /// Confirm the stream file of `entry` is intact with its checksum.
+    /// Return the problem found on the stream file, or `None` if it is intact.
+    fn check(&self, entry: &StreamEntry) -> Option<String> {
+        let path = self.directory.join(&entry.file);
+        if entry.sha256.is_empty() {
+            elephant_log::warn!("'{}' has no checksum to verify", path.display());
+            return None;
+        }
+
+        let checksum = File::open(&path)
+                .and_then(|mut file| Relay::checksum(&mut file));
+        match checksum {
+            Ok(transfer) if transfer.sha256 == entry.sha256 => {
+                elephant_log::info!("verified: {}", path.display());
+                None
+            },
+            Ok(transfer) => Some(format!("'{}' has the checksum {}, expected {}",
+                    path.display(), transfer.sha256, entry.sha256)),
+            Err(e) => Some(format!("'{}' cannot be read: {e}", path.display())),
+        }
+    }
+
+    /// Resolve the streams replaying `snapshot` of the `dataset` filesystem,
+    /// a full stream followed by the incremental streams in the order they are received.
+    /// Return an error if a stream of the chain is missing on the manifest or on the directory.
+    pub fn chain(&self, dataset: &str, snapshot: &Snapshot) -> Result<Vec<StreamEntry>, String> {
+        let entries = self.entries(dataset);
+        let find = |short_name: &str| entries.iter().rev().find(|e| e.snapshot == short_name);
+
+        let mut chain = Vec::new();
+        let mut short_name = snapshot.short_name();
+        loop {
+            let entry = match find(&short_name) {
+                Some(entry) => entry.clone(),
+                None => return Err(format!("'{}' stream store has no stream of {dataset}@{short_name} in the chain of {}",
+                        self.directory.display(), snapshot.name())),
+            };
+
+            let path = self.directory.join(&entry.file);
+            if !path.is_file() {
+                return Err(format!("'{}' stream file of {} is missing", path.display(), entry.snapshot_name()));
+            }
+
+            let base = entry.base.clone();
+            chain.push(entry);
+            match base {
+                // a stream never depends on itself or a later stream.
+                Some(base) if chain.iter().any(|e| e.snapshot == base) => {
+                    return Err(format!("'{}' stream store has a loop on the chain of {}",
+                            self.directory.display(), snapshot.name()));
+                },
+                Some(base) => short_name = base,
+                None => break,
+            }
+        }
+
+        chain.reverse();
+        Ok(chain)
+    }
+
+    /// Restore `snapshot` of the `dataset` filesystem to the `target` filesystem on this machine
+    /// by replaying the chain of its streams.
+    /// The existing `target` filesystem is overwritten only if `force` is true and it has no child filesystem,
+    /// it is replaced after the whole chain is received, releasing the holds of the archives on it.
+    /// Return the snapshot instance restored.
+    pub fn restore(&self, dataset: &str, snapshot: &Snapshot, target: &str, force: bool,
+            option: &SendOption) -> Result<Snapshot, String> {
+        let chain = self.chain(dataset, snapshot)?;
+
+        // all of the stream files are checked before receiving any of them.
+        let problems: Vec<String> = chain.iter().filter_map(|entry| self.check(entry)).collect();
+        if !problems.is_empty() {
+            return Err(format!("The chain of {} is broken: {}", snapshot.name(), problems.join(", ")));
+        }
+
+        // the existing filesystem is replaced only after the whole chain is received.
+        let receiving = Filesystem::receiving(target, force)?;
+        for entry in chain.iter() {
+            let path = self.directory.join(&entry.file);
+            receiving.receive_file(&path, &entry.codec.decoders(), option)?;
+            elephant_log::info!("replayed: {} of {}", entry.snapshot_name(), snapshot.name());
+        }
+        let restored = receiving.replace(target)?;
+
+        Ok(restored.snapshot_of(snapshot))
+    }
+
+    /// Append `entry` to the manifest file.
+    fn record(&self, entry: StreamEntry) -> Result<(), String> {
+        // the lines of the streams written in parallel are appended one at a time.
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let manifest = self.directory.join(MANIFEST_FILE);
+        let mut file = OpenOptions::new().create(true).append(true).open(&manifest)
+                .map_err(|e| format!("Failed to open '{}': {e}", manifest.display()))?;
+        writeln!(file, "{}", entry.format())
+                .and_then(|_| file.sync_all())
+                .map_err(|e| format!("Failed to write '{}': {e}", manifest.display()))?;
+
+        entries.push(entry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(snapshot: &str, base: Option<&str>) -> StreamEntry {
+        StreamEntry {
+            dataset: "tank/data".to_string(),
+            snapshot: snapshot.to_string(),
+            guid: "1234".to_string(),
+            base: base.map(|b| b.to_string()),
+            bytes: 4096,
+            file: format!("tank/data/{snapshot}.zfs.zst"),
+            codec: StreamCodec::new("zstd", NONE).unwrap(),
+            sha256: "ab".repeat(32),
+        }
+    }
+
+    #[test]
+    fn codec_decoders_reverse_encoders() {
+        let codec = StreamCodec::new("zstd", "gpg:backup@example.com").unwrap();
+
+        assert_eq!(codec.encoders(), ["zstd -c -q".to_string(), format!("{GPG_ENCRYPT} backup@example.com")]);
+        assert_eq!(codec.decoders(), [GPG_DECRYPT, "zstd -d -c -q"]);
+        assert_eq!(codec.extension(), "zfs.zst.gpg");
+
+        let plain = StreamCodec::new(NONE, NONE).unwrap();
+        assert!(plain.encoders().is_empty() && plain.decoders().is_empty());
+        assert_eq!(plain.extension(), "zfs");
+
+        assert!(StreamCodec::new("lz4", NONE).is_err());
+        assert!(StreamCodec::new("zstd", "aes:secret").is_err());
+    }
+
+    #[test]
+    fn codec_round_trip() {
+        let codec = StreamCodec::new("gzip", NONE).unwrap();
+        let pipeline = [codec.encoders(), codec.decoders()].concat().join(" | ");
+        let stream: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+
+        let mut child = Command::new("sh").arg("-c").arg(&pipeline)
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .spawn().unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        let written = stream.clone();
+        let writer = std::thread::spawn(move || stdin.write_all(&written));
+        let output = child.wait_with_output().unwrap();
+        writer.join().unwrap().unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, stream);
+    }
+
+    /// Make a stream store in a temporary directory with the manifest of `entries` and their stream files,
+    /// except the stream files of the snapshots in `missings`.
+    fn store(name: &str, entries: &[StreamEntry], missings: &[&str]) -> StreamStore {
+        let directory = std::env::temp_dir().join(format!("elephant-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("tank/data")).unwrap();
+
+        let manifest: Vec<String> = entries.iter().map(|e| e.format()).collect();
+        fs::write(directory.join(MANIFEST_FILE), manifest.join("\n")).unwrap();
+        for entry in entries.iter().filter(|e| !missings.contains(&e.snapshot.as_str())) {
+            fs::write(directory.join(&entry.file), b"stream").unwrap();
+        }
+
+        StreamStore::open(directory.to_str().unwrap()).unwrap()
+    }
+
+    fn chain(store: &StreamStore, short_name: &str) -> Result<Vec<String>, String> {
+        let snapshot = Snapshot::new(&format!("tank/data@{short_name}"));
+        let chain = store.chain("tank/data", &snapshot);
+        fs::remove_dir_all(&store.directory).unwrap();
+
+        chain.map(|entries| entries.into_iter().map(|e| e.snapshot).collect())
+    }
+
+    #[test]
+    fn chain_from_full_stream() {
+        let entries = [entry("a", None), entry("b", Some("a")), entry("c", Some("b")), entry("d", Some("b"))];
+
+        let names = |names: &[&str]| Ok(names.iter().map(|n| n.to_string()).collect());
+
+        assert_eq!(chain(&store("chain-latest", &entries, &[]), "c"), names(&["a", "b", "c"]));
+        assert_eq!(chain(&store("chain-branch", &entries, &[]), "d"), names(&["a", "b", "d"]));
+        assert_eq!(chain(&store("chain-full", &entries, &[]), "a"), names(&["a"]));
+    }
+
+    #[test]
+    fn chain_broken() {
+        let entries = [entry("a", None), entry("b", Some("a")), entry("c", Some("x"))];
+        let unknown = chain(&store("chain-unknown", &entries, &[]), "z").unwrap_err();
+        let missing_entry = chain(&store("chain-entry", &entries, &[]), "c").unwrap_err();
+        let missing_file = chain(&store("chain-file", &entries, &["a"]), "b").unwrap_err();
+
+        assert!(unknown.contains("has no stream of tank/data@z"), "{unknown}");
+        assert!(missing_entry.contains("has no stream of tank/data@x"), "{missing_entry}");
+        assert!(missing_file.contains("is missing"), "{missing_file}");
+    }
+
+    #[test]
+    fn chain_loop() {
+        let entries = [entry("a", Some("b")), entry("b", Some("a"))];
+        let looped = chain(&store("chain-loop", &entries, &[]), "b").unwrap_err();
+
+        assert!(looped.contains("has a loop"), "{looped}");
+    }
+
+    #[test]
+    fn hold_tag_without_spaces() {
+        // the directory on a removable disk often has spaces like "My Passport".
+        let store = store("My Passport", &[], &[]);
+        let tag = store.hold_tag();
+        fs::remove_dir_all(&store.directory).unwrap();
+
+        assert!(tag.starts_with(HOLD_TAG_PREFIX), "{tag}");
+        assert!(!tag.contains(char::is_whitespace), "{tag}");
+    }
+
+    #[test]
+    fn entry_format_parse() {
+        let full = entry("elephant-2022-1101-100000", None);
+        let incremental = entry("elephant-2022-1102-100000", Some("elephant-2022-1101-100000"));
+
+        assert_eq!(StreamEntry::parse(&full.format()), full);
+        assert_eq!(StreamEntry::parse(&incremental.format()), incremental);
+    }
+
+    #[test]
+    fn entry_parse_old_line() {
+        // a line written before the codec and the checksum were recorded, with an unknown field.
+        let entry = StreamEntry::parse("dataset=tank/data\tsnapshot=elephant-2022-1101-100000\tguid=1234\tbase=-\
+                \tbytes=4096\tfile=tank/data/elephant-2022-1101-100000.zfs\tcolor=blue");
+
+        assert_eq!(entry.dataset, "tank/data");
+        assert_eq!(entry.base, None);
+        assert_eq!(entry.bytes, 4096);
+        assert_eq!(entry.codec, StreamCodec::new(NONE, NONE).unwrap());
+        assert_eq!(entry.sha256, "");
+        assert_eq!(entry.snapshot_name(), "tank/data@elephant-2022-1101-100000");
+    }
+}
@@ -2,8 +2,22 @@
 // All rights reserved.
 // Elephant Archive is licensed under BSD 2-Clause License.
 
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, PoisonError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{self, Path, PathBuf};
+use chrono::{Local, Duration};
+
+use crate::configure;
+use crate::recovery;
 use crate::argument::{Argument, ArchiverCommand};
-use crate::zfs::{Filesystem, Snapshot};
+use crate::store::StreamStore;
+use crate::zfs::{Filesystem, Snapshot, Retention, Driver, Transport, SendOption, Mapping, Selector, ChangeKind};
+use crate::zfs::filesystem::{USER_PROPERTY_PREFIX, HOLD_TAG_PREFIX};
+use crate::zfs::relay::{parse_bytes, format_bytes};
 
 pub trait SubCommand {
 
@@ -30,12 +44,36 @@ pub trait SubCommand {
         let filesystems = &args.filesystem;
 
         let f = filesystems.iter().find(|x|!Filesystem::exist(x));
-        let result = match f {
+        match f {
             Some(filesystem) => Err(format!("The '{filesystem}' ZFS filesystem is not found")),
             None => Ok(()),
-        };
+        }
+    }
+
+    /// Confirm the ZFS filesystem of the archive is accessible or not,
+    /// and confirm the replications of the ZFS filesystems are named without collisions.
+    fn accessible_archive_default(&self) -> Result<(), String> {
+        let args = Argument::global();
+        let archive = &args.archive;
+
+        let transport = Transport::from(args.transport.as_deref());
+        Driver::init_archive(transport);
+
+        if !Filesystem::exist_archive(archive) {
+            return Err(format!("The '{archive}' ZFS filesystem is not found"));
+        }
 
-        result
+        Mapping::init(Mapping::parse(&args.mappings)?);
+        let mut targets: Vec<String> = Vec::new();
+        for fs_name in args.filesystem.iter() {
+            let target = Mapping::global().target(archive, fs_name);
+            if targets.contains(&target) {
+                return Err(format!("The '{fs_name}' ZFS filesystem collides on the '{target}' replication, add a mapping rule"));
+            }
+            targets.push(target);
+        }
+
+        Ok(())
     }
 
 }
@@ -45,6 +83,12 @@ pub fn from(command: &ArchiverCommand) -> Box<dyn SubCommand> {
     elephant_log::error!("{:?}", command);
 
     let subcommand: Box<dyn SubCommand> = match command {
+        ArchiverCommand::Archive { .. } => {
+            Box::new( ArchiveCommand {} )
+        },
+        ArchiverCommand::Verify { .. } => {
+            Box::new( VerifyCommand {} )
+        },
         ArchiverCommand::Snapshot { .. } => {
             Box::new( SnapshotCommand {} )
         },
@@ -54,12 +98,136 @@ pub fn from(command: &ArchiverCommand) -> Box<dyn SubCommand> {
         ArchiverCommand::Show { .. } => {
             Box::new( ShowCommand {} )
         },
-        _ => { elephant_log::error!("Not Implemented yet"); panic!() },
+        ArchiverCommand::Restore { .. } => {
+            Box::new( RestoreCommand {} )
+        },
+        ArchiverCommand::Rollback { .. } => {
+            Box::new( RollbackCommand {} )
+        },
+        ArchiverCommand::Clone { .. } => {
+            Box::new( CloneCommand {} )
+        },
+        ArchiverCommand::Diff { .. } => {
+            Box::new( DiffCommand {} )
+        },
     };
 
     subcommand
 }
 
+/// Process the `fs_names` ZFS filesystems with `job` on as many worker threads as the `jobs` argument.
+/// Return the results of `job` in the order of `fs_names`.
+fn parallel(fs_names: &[String], job: impl Fn(&str) -> Result<(), String> + Sync)
+        -> Vec<(String, Result<(), String>)> {
+    let args = Argument::global();
+    let workers = args.jobs.clamp(1, fs_names.len().max(1));
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(fs_name) = fs_names.get(index) else {
+                    break;
+                };
+
+                // a panic fails only the filesystem processed on it.
+                let result = panic::catch_unwind(AssertUnwindSafe(|| job(fs_name)))
+                        .unwrap_or_else(|_| Err(format!("The '{fs_name}' ZFS filesystem stopped unexpectedly")));
+                results.lock().unwrap_or_else(PoisonError::into_inner).push((index, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap_or_else(PoisonError::into_inner);
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(index, result)| (fs_names[index].clone(), result)).collect()
+}
+
+/// Report the results of the ZFS filesystems processed with `action`.
+/// Return an error if any of the filesystems failed.
+fn report(action: &str, results: Vec<(String, Result<(), String>)>) -> Result<(), String> {
+    let total = results.len();
+    let mut failures = 0;
+    for (fs_name, result) in results {
+        match result {
+            Ok(()) => elephant_log::info!("{action}: '{fs_name}' succeeded"),
+            Err(message) => {
+                elephant_log::error!("{action}: '{fs_name}' failed: {message}");
+                failures += 1;
+            },
+        }
+    }
+
+    if failures > 0 {
+        return Err(format!("{failures} of {total} filesystems failed to {action}"));
+    }
+
+    Ok(())
+}
+
+/// Ask `question` on the terminal, and return the answer is yes or not.
+fn confirm(question: &str) -> Result<bool, String> {
+    print!("{question} [y/N] ");
+    io::stdout().flush().map_err(|e| e.to_string())?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).map_err(|e| format!("Failed to read the answer: {e}"))?;
+
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
+/// Select one of `snapshots` of the `name` filesystem with the `snapshot` argument,
+/// the newest one unless it is specified.
+fn select_snapshot(name: &str, snapshots: &[Snapshot], action: &str) -> Result<Snapshot, String> {
+    let args = Argument::global();
+
+    let selector = match &args.snapshot {
+        Some(expression) => Selector::parse(expression)?,
+        None => Selector::Latest,
+    };
+
+    match (selector.select(snapshots), &args.snapshot) {
+        (Some(snapshot), _) => Ok(snapshot.clone()),
+        (None, Some(expression)) => Err(format!("The '{name}' ZFS filesystem has no snapshot matching '{expression}'")),
+        (None, None) => Err(format!("The '{name}' ZFS filesystem has no snapshot to {action}")),
+    }
+}
+
+/// Get the retention policy of the `replica` filesystem on the archive,
+/// overridden with the `elephant:keep-*` properties set on the archive.
+fn archive_retention(replica: &Filesystem) -> Result<Retention, String> {
+    Retention::archive(|key| replica.property(&format!("{USER_PROPERTY_PREFIX}{key}")))
+}
+
+/// Display the snapshots of `filesystem` in the generations of `retention` and its bookmarks.
+fn show_snapshots(filesystem: &Filesystem, retention: &Retention) {
+    let snapshots = filesystem.snapshots();
+    let generation = Snapshot::generation(&snapshots, retention);
+
+    elephant_log::display!("Young snapshots:");
+    for snapshot in generation.young {
+        elephant_log::display!("{}", snapshot.name());
+    }
+
+    elephant_log::display!("Middle snapshots:");
+    for snapshot in generation.middle {
+        elephant_log::display!("{}", snapshot.name());
+    }
+
+    elephant_log::display!("Old snapshots:");
+    for snapshot in generation.old {
+        elephant_log::display!("{}", snapshot.name());
+    }
+
+    elephant_log::display!("Bookmarks:");
+    for bookmark in filesystem.bookmarks() {
+        elephant_log::display!("{}", bookmark.name());
+    }
+}
+
 pub struct ArchiveCommand;
 
 impl SubCommand for ArchiveCommand {
@@ -69,25 +237,217 @@ impl SubCommand for ArchiveCommand {
 
         let args = Argument::global();
         let archive = &args.archive;
-        let result = if Filesystem::exist(archive)
-            { Ok(()) } else 
-            { Err(format!("The '{archive}' ZFS filesystem is not found")) };
 
-        result
+        // a replication stream received with -F destroys the snapshots and the filesystems gone on the source.
+        if args.rollback && args.recursive {
+            return Err("The '--rollback' option cannot be used with the '--recursive' option".to_string());
+        }
+
+        // a stream store is a directory on this machine.
+        if StreamStore::is_store(archive) {
+            if args.purge {
+                return Err(format!("The '{archive}' directory cannot be purged, the streams depend on each other"));
+            }
+            StreamStore::open(archive)?;
+            return Ok(());
+        }
+
+        SubCommand::accessible_archive_default(self)?;
+
+        // confirm the interrupted receives on the archive can be resumed.
+        let replication = Filesystem::from_archive(archive)?;
+        for fs_name in args.filesystem.iter() {
+            let original = Filesystem::from(fs_name)?;
+            let mut replica = replication.replica(&original);
+
+            let token = match replica.resume_token() {
+                Some(token) => token,
+                None => continue,
+            };
+
+            // the plan of a dry run notes the interrupted receive.
+            if args.dryrun {
+                continue;
+            }
+
+            if args.abort {
+                elephant_log::display!("Abort the interrupted archive on '{}'", replica.name());
+                replica.abort_receive()?;
+            }
+            else if let Err(message) = replica.check_resume(&original, &token) {
+                return Err(format!("{message}, abort it with the '--abort' option"));
+            }
+        }
+
+        Ok(())
     }
 
     fn run(&self) -> Result<(), String> {
         let args = Argument::global();
         let fs_names = &args.filesystem;
 
-        // display the snapshots every the filesystem.
-        for fs_name in fs_names {
-            let mut filesystem = Filesystem::from(fs_name)?;
+        if StreamStore::is_store(&args.archive) {
+            let mut store = StreamStore::open(&args.archive)?;
+            if args.dryrun {
+                let option = Self::send_option(|key| store.setting(key))?;
+                // the plans are shown one by one not to mix their lines.
+                let results = fs_names.iter().map(|fs_name| {
+                    let original = Filesystem::from(fs_name);
+                    let plan = original.and_then(|original| store.plan(&original, &option));
+                    (fs_name.clone(), plan.map(|plan| plan.show()))
+                }).collect();
+
+                return report("plan", results);
+            }
+
+            store.configure(args.compress.as_deref(), args.recipient.as_deref())?;
+            let option = Self::send_option(|key| store.setting(key))?;
+
+            // the streams of the filesystems are written in parallel, the manifest is locked only to record them.
+            let results = parallel(fs_names, |fs_name| {
+                let original = Filesystem::from(fs_name)?;
+                let snapshot = store.archive(&original, &option)?;
+                elephant_log::display!("Archived a snapshot: {}", snapshot.name());
+                Ok(())
+            });
+
+            return report("archive", results);
+        }
+
+        let replication = Filesystem::from_archive(&args.archive)?;
+        let option = Self::send_option(|key| replication.property(&format!("{USER_PROPERTY_PREFIX}{key}")))?;
+
+        // show the plans instead of archiving on a dry run.
+        if args.dryrun {
+            // the plans are shown one by one not to mix their lines.
+            let results = fs_names.iter().map(|fs_name| {
+                let original = Filesystem::from(fs_name);
+                let plan = original.and_then(|original| Filesystem::plan(&replication, &original, &option));
+                (fs_name.clone(), plan.map(|plan| plan.show()))
+            }).collect();
+
+            return report("plan", results);
+        }
+
+        // archive the newest snapshot every the filesystems.
+        let results = parallel(fs_names, |fs_name| {
+            let original = Filesystem::from(fs_name)?;
+            let snapshot = Filesystem::archive(&replication, &original, &option)?;
+            elephant_log::display!("Archived a snapshot: {}", snapshot.name());
+
+            // the snapshot archived is the base of the next incremental stream.
+            if args.purge {
+                let mut replica = replication.replica(&original);
+                let destroys = replica.purge_snapshots(&archive_retention(&replica)?, &[snapshot]);
+                elephant_log::display!("Destroy snapshots: {:?}", destroys);
+            }
+
+            Ok(())
+        });
+
+        report("archive", results)
+    }
+}
 
+impl ArchiveCommand {
+    /// Make the options to send the streams from the arguments.
+    /// The rate limit and the buffer are the `ratelimit` and `buffer` settings of the archive
+    /// unless they are specified with the arguments.
+    fn send_option(setting: impl Fn(&str) -> Option<String>) -> Result<SendOption, String> {
+        let args = Argument::global();
+
+        let rate_limit = args.rate_limit.clone().or_else(|| setting("ratelimit"));
+        let buffer = args.buffer.clone().or_else(|| setting("buffer"));
+
+        let rate_limit = rate_limit.map(|r| parse_bytes(&r)).transpose()?;
+        if rate_limit == Some(0) {
+            return Err("The rate limit must be more than 0 bytes per second".to_string());
+        }
+
+        if let Some(property) = args.overrides.iter().find(|p| !p.contains('=')) {
+            return Err(format!("'{property}' is not like PROPERTY=VALUE"));
         }
 
+        // the properties making the archive mount over the originals are excluded.
+        let properties = args.recursive || args.properties;
+        let mut excludes = args.excludes.clone();
+        if properties {
+            for property in configure::RECEIVE_EXCLUDE_PROPERTIES {
+                let overridden = args.overrides.iter().any(|p| p.starts_with(&format!("{property}=")));
+                if !overridden && !excludes.iter().any(|e| e == property) {
+                    excludes.push(property.to_string());
+                }
+            }
+        }
+
+        Ok(SendOption {
+            progress: args.progress,
+            parallel: args.jobs > 1 && args.filesystem.len() > 1,
+            rate_limit,
+            buffer: buffer.map(|b| parse_bytes(&b)).transpose()?,
+            recursive: args.recursive,
+            properties: args.properties,
+            rollback: args.rollback,
+            overrides: args.overrides.clone(),
+            excludes,
+            ..SendOption::default()
+        })
+    }
+}
+
+/// A function finding the problems on the archive of an original filesystem.
+type Verifier = Box<dyn Fn(&Filesystem) -> Vec<String> + Sync>;
+
+pub struct VerifyCommand;
+
+impl SubCommand for VerifyCommand {
+
+    fn accessible_filesystem(&self) -> Result<(), String> {
+        SubCommand::accessible_filesystem_default(self)?;
+
+        let args = Argument::global();
+        let archive = &args.archive;
+        if StreamStore::is_store(archive) {
+            StreamStore::open(archive)?;
+            return Ok(());
+        }
+
+        SubCommand::accessible_archive_default(self)?;
+
         Ok(())
     }
+
+    fn run(&self) -> Result<(), String> {
+        let args = Argument::global();
+        let fs_names = &args.filesystem;
+
+        let verify: Verifier = if StreamStore::is_store(&args.archive) {
+            let store = StreamStore::open(&args.archive)?;
+            Box::new(move |original| store.verify(&original.name()))
+        }
+        else {
+            let replication = Filesystem::from_archive(&args.archive)?;
+            Box::new(move |original| Filesystem::verify(&replication, original))
+        };
+
+        // verify the archive every the filesystems.
+        let results = parallel(fs_names, |fs_name| {
+            let original = Filesystem::from(fs_name)?;
+            let problems = verify(&original);
+
+            for problem in problems.iter() {
+                elephant_log::error!("{problem}");
+            }
+            if !problems.is_empty() {
+                return Err(format!("{} problems are found on the archive", problems.len()));
+            }
+
+            elephant_log::display!("Verified the archive of '{fs_name}'");
+            Ok(())
+        });
+
+        report("verify", results)
+    }
 }
 
 pub struct SnapshotCommand;
@@ -113,6 +473,24 @@ pub struct PurgeCommand;
 
 impl SubCommand for PurgeCommand {
 
+    fn accessible_filesystem(&self) -> Result<(), String> {
+        SubCommand::accessible_filesystem_default(self)?;
+
+        let args = Argument::global();
+        let archive = &args.archive;
+        if archive.is_empty() {
+            return Ok(());
+        }
+
+        if StreamStore::is_store(archive) {
+            return Err(format!("The '{archive}' directory cannot be purged, the streams depend on each other"));
+        }
+
+        SubCommand::accessible_archive_default(self)?;
+
+        Ok(())
+    }
+
     fn run(&self) -> Result<(), String> {
         let args = Argument::global();
         let fs_names = &args.filesystem;
@@ -120,10 +498,360 @@ impl SubCommand for PurgeCommand {
         // purge some snapshots every the filesystems.
         for fs_name in fs_names {
             let mut filesystem = Filesystem::from(fs_name)?;
-            let destroys = filesystem.purge_snapshots();
+            if args.archive.is_empty() {
+                let destroys = filesystem.purge_snapshots(&Retention::original(), &[]);
+                elephant_log::display!("Destroy snapshots: {:?}", destroys);
+                continue;
+            }
+
+            // keep the base of the next incremental stream on the both sides.
+            let replication = Filesystem::from_archive(&args.archive)?;
+            let mut replica = replication.replica(&filesystem);
+            // a bookmark as the base keeps the snapshot with the same short name on the archive.
+            let protects: Vec<Snapshot> = filesystem.newest_base(&replica.snapshots())
+                    .into_iter().collect();
+
+            let destroys = filesystem.purge_snapshots(&Retention::original(), &protects);
             elephant_log::display!("Destroy snapshots: {:?}", destroys);
+
+            if replica.is_present() {
+                let destroys = replica.purge_snapshots(&archive_retention(&replica)?, &protects);
+                elephant_log::display!("Destroy snapshots on the archive: {:?}", destroys);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub struct RestoreCommand;
+
+impl SubCommand for RestoreCommand {
+
+    fn accessible_filesystem(&self) -> Result<(), String> {
+        // the original filesystem may be lost, only its replication is required.
+        let args = Argument::global();
+        if args.file.is_some() {
+            return Ok(());
+        }
+
+        // a stream store is a directory on this machine storing the streams of the original filesystem.
+        if StreamStore::is_store(&args.archive) {
+            let store = StreamStore::open(&args.archive)?;
+            if store.entries(&args.filesystem[0]).is_empty() {
+                return Err(format!("The '{}' directory has no stream of the '{}' ZFS filesystem",
+                        args.archive, args.filesystem[0]));
+            }
+            return Ok(());
+        }
+
+        SubCommand::accessible_archive_default(self)?;
+        Self::replica()?;
+
+        Ok(())
+    }
+
+    fn run(&self) -> Result<(), String> {
+        let args = Argument::global();
+        if let Some(file) = &args.file {
+            return Self::restore_file(file);
+        }
+
+        if StreamStore::is_store(&args.archive) {
+            return Self::restore_store();
+        }
+
+        let replica = Self::replica()?;
+        if args.list {
+            show_snapshots(&replica, &archive_retention(&replica)?);
+            return Ok(());
+        }
+
+        let snapshot = select_snapshot(&replica.name(), &replica.snapshots(), "restore")?;
+        let target = args.to.as_ref().unwrap_or(&args.filesystem[0]);
+        let option = SendOption {
+            progress: args.progress,
+            ..SendOption::default()
+        };
+        let restored = replica.restore(&snapshot, target, args.force, &option)?;
+        elephant_log::display!("Restored a snapshot: {}, mount it with 'zfs mount {target}'", restored.name());
+
+        Ok(())
+    }
+}
+
+impl RestoreCommand {
+    /// Restore a snapshot of the original filesystem by replaying its streams on the stream store.
+    fn restore_store() -> Result<(), String> {
+        let args = Argument::global();
+        let dataset = &args.filesystem[0];
+
+        let store = StreamStore::open(&args.archive)?;
+        if args.list {
+            for entry in store.entries(dataset) {
+                let kind = match &entry.base {
+                    Some(base) => format!("incremental from {base}"),
+                    None => "full".to_string(),
+                };
+                elephant_log::display!("{} ({kind}, {})", entry.snapshot_name(), format_bytes(entry.bytes));
+            }
+            return Ok(());
+        }
+
+        let snapshot = select_snapshot(dataset, &store.snapshots(dataset), "restore")?;
+        let target = args.to.as_ref().unwrap_or(dataset);
+        let option = SendOption {
+            progress: args.progress,
+            ..SendOption::default()
+        };
+        let restored = store.restore(dataset, &snapshot, target, args.force, &option)?;
+        elephant_log::display!("Restored a snapshot: {}, mount it with 'zfs mount {target}'", restored.name());
+
+        Ok(())
+    }
+
+    /// Restore the `file` file or directory from a snapshot of the filesystem containing it.
+    /// The file is copied next to the original file unless the destination is specified.
+    fn restore_file(file: &str) -> Result<(), String> {
+        let args = Argument::global();
+
+        let path = path::absolute(file).map_err(|e| format!("'{file}' is not a path: {e}"))?;
+        let path = Self::resolve(&path);
+        let (filesystem, relative) = Filesystem::containing(&path)?;
+        let snapshot = select_snapshot(&filesystem.name(), &filesystem.snapshots(), "restore")?;
+
+        let source = filesystem.snapshot_path(&snapshot, &relative);
+        if fs::symlink_metadata(&source).is_err() {
+            return Err(format!("'{}' is not found in {}", relative.display(), snapshot.name()));
+        }
+
+        // the file is restored into the existing directory restored to.
+        let name = path.file_name().unwrap_or_default();
+        let destination = match &args.to {
+            Some(to) if Path::new(to).is_dir() => Path::new(to).join(name),
+            Some(to) => PathBuf::from(to),
+            None => path.with_file_name(format!("{}.{}", name.to_string_lossy(), snapshot.short_name())),
+        };
+
+        if fs::symlink_metadata(&destination).is_ok() && !args.force {
+            return Err(format!("'{}' exists, restore it with the '--force' option to overwrite it",
+                    destination.display()));
+        }
+
+        let count = recovery::copy_preserving(&source, &destination, args.force)?;
+        elephant_log::display!("Restored {count} files from {} to {}", source.display(), destination.display());
+
+        Ok(())
+    }
+
+    /// Resolve the symbolic links to the nearest existing directory containing `path`,
+    /// like a home directory linked into the mountpoint of a ZFS filesystem.
+    /// The file itself is not resolved, it may be deleted or be a symbolic link restored as it is.
+    fn resolve(path: &Path) -> PathBuf {
+        for ancestor in path.ancestors().skip(1) {
+            if let Ok(resolved) = fs::canonicalize(ancestor) {
+                let rest = path.strip_prefix(ancestor).unwrap_or(path);
+                return resolved.join(rest);
+            }
+        }
+
+        path.to_path_buf()
+    }
+
+    /// Get the replication of the original filesystem on the archive.
+    fn replica() -> Result<Filesystem, String> {
+        let args = Argument::global();
+        let name = Mapping::global().target(&args.archive, &args.filesystem[0]);
+
+        Filesystem::from_archive(&name)
+    }
+}
+
+pub struct RollbackCommand;
+
+impl SubCommand for RollbackCommand {
+
+    fn run(&self) -> Result<(), String> {
+        let args = Argument::global();
+
+        let mut filesystem = Filesystem::from(&args.filesystem[0])?;
+        let snapshot = select_snapshot(&filesystem.name(), &filesystem.snapshots(), "roll back to")?;
+
+        // the snapshots after the snapshot rolled back to are destroyed with their clones.
+        let newers = filesystem.snapshots_after(&snapshot);
+        let clones = filesystem.clones(&newers);
+        let holds = filesystem.holds(&newers);
+        let bookmarks: Vec<Snapshot> = filesystem.bookmarks().into_iter()
+                .filter(|b| b.datetime() > snapshot.datetime())
+                .collect();
+
+        // the snapshots held by others are kept, only the holds of Elephant Archive are released.
+        let others: Vec<String> = holds.iter()
+                .filter(|(_, tag)| !tag.starts_with(HOLD_TAG_PREFIX))
+                .map(|(held, tag)| format!("{held} ({tag})"))
+                .collect();
+        if !others.is_empty() {
+            return Err(format!("The snapshots after '{}' are held, release them first: {}",
+                    snapshot.name(), others.join(" ")));
+        }
+
+        elephant_log::display!("Roll back {} to {}", filesystem.name(), snapshot.name());
+        for newer in newers.iter() {
+            elephant_log::display!("  destroy the snapshot {newer}");
+        }
+        for bookmark in bookmarks.iter() {
+            elephant_log::display!("  destroy the bookmark {}", bookmark.name());
+        }
+        for clone in clones.iter() {
+            elephant_log::display!("  destroy the clone {clone}");
+        }
+        for (held, tag) in holds.iter() {
+            elephant_log::display!("  release the hold {tag} from {held}");
+        }
+        // the copy is beside the filesystem, or under the root filesystem of the pool that has no sibling.
+        let name = filesystem.name();
+        let safety = Snapshot::generate_name(&name);
+        let short_name = Snapshot::new(&safety).short_name();
+        let copy = match name.contains('/') {
+            true => format!("{name}.{short_name}"),
+            false => format!("{name}/{name}.{short_name}"),
+        };
+        elephant_log::display!("  copy the current data to {copy} beforehand");
+
+        // the copy takes the space of the whole current data.
+        let referenced = filesystem.property("referenced").and_then(|r| r.parse::<u64>().ok());
+        if let (Some(referenced), Some(available)) = (referenced, filesystem.available()) {
+            if referenced > available {
+                return Err(format!("The copy of {name} needs {} but {} is available, roll it back with enough space",
+                        format_bytes(referenced), format_bytes(available)));
+            }
+        }
+
+        if args.dryrun {
+            return Ok(());
         }
 
+        if !args.yes && !confirm(&format!("Roll back {}?", filesystem.name()))? {
+            elephant_log::display!("Cancelled the rollback of {}", filesystem.name());
+            return Ok(());
+        }
+
+        // the safety snapshot is destroyed by the rollback, its copy keeps the current data.
+        // the descendant filesystems are not rolled back, they have no safety snapshot.
+        let safety = filesystem.take_own_snapshot();
+        filesystem.restore(&safety, &copy, false, &SendOption::default())?;
+        elephant_log::display!("Copied {} to {copy}", safety.name());
+
+        for (held, tag) in holds.iter() {
+            filesystem.release(held, tag)?;
+        }
+        filesystem.rollback(&snapshot, !clones.is_empty())?;
+
+        elephant_log::display!("Rolled back {} to {}, the data before the rollback is on {copy}",
+                filesystem.name(), snapshot.name());
+
+        Ok(())
+    }
+}
+
+pub struct CloneCommand;
+
+impl SubCommand for CloneCommand {
+
+    fn run(&self) -> Result<(), String> {
+        let args = Argument::global();
+        if args.cleanup {
+            return Self::cleanup();
+        }
+
+        let filesystem = Filesystem::from(&args.filesystem[0])?;
+        let snapshot = select_snapshot(&filesystem.name(), &filesystem.snapshots(), "clone")?;
+
+        let pool = filesystem.name().split('/').next().unwrap().to_string();
+        let parent = args.parent.clone().unwrap_or_else(|| format!("{pool}/{}", configure::CLONE_PARENT));
+        let expires = Local::now() + Duration::hours(args.expire);
+        let mode = if args.writable { "read-write" } else { "read-only" };
+
+        if args.dryrun {
+            elephant_log::display!("Clone {} under {parent} {mode} until {}", snapshot.name(), expires.format("%Y-%m-%d %H:%M"));
+            return Ok(());
+        }
+
+        let clone = filesystem.clone_snapshot(&snapshot, &parent, args.writable, expires)?;
+        let mountpoint = clone.property("mountpoint").unwrap_or_default();
+        elephant_log::display!("Cloned {} to {} mounted {mode} on {mountpoint} until {}",
+                snapshot.name(), clone.name(), expires.format("%Y-%m-%d %H:%M"));
+
+        Ok(())
+    }
+}
+
+impl CloneCommand {
+    /// Destroy the clones for inspection expired.
+    fn cleanup() -> Result<(), String> {
+        let args = Argument::global();
+
+        let now = Local::now();
+        let expireds: Vec<String> = Filesystem::inspection_clones().into_iter()
+                .filter(|(_, expires)| *expires <= now)
+                .map(|(name, _)| name)
+                .collect();
+
+        let mut results = Vec::new();
+        for name in expireds {
+            if args.dryrun {
+                elephant_log::display!("Destroy the expired clone {name}");
+                continue;
+            }
+
+            let result = Filesystem::from(&name).and_then(|clone| clone.destroy());
+            if result.is_ok() {
+                elephant_log::display!("Destroyed the expired clone {name}");
+            }
+            results.push((name, result));
+        }
+
+        report("clean up", results)
+    }
+}
+
+pub struct DiffCommand;
+
+impl SubCommand for DiffCommand {
+
+    fn run(&self) -> Result<(), String> {
+        let args = Argument::global();
+
+        let filesystem = Filesystem::from(&args.filesystem[0])?;
+        let snapshots = filesystem.snapshots();
+        let snapshot = select_snapshot(&filesystem.name(), &snapshots, "compare")?;
+
+        // the other snapshot is on the same filesystem, with or without its name.
+        let other = match &args.other {
+            Some(other) => {
+                let selector = other.strip_prefix(&format!("{}@", filesystem.name())).unwrap_or(other);
+                let found = Selector::parse(selector)?.select(&snapshots).cloned();
+                Some(found.ok_or_else(|| format!("The '{}' ZFS filesystem has no snapshot matching '{selector}'",
+                        filesystem.name()))?)
+            },
+            None => None,
+        };
+
+        let changes = filesystem.diff(&snapshot, other.as_ref())?;
+
+        let to = other.as_ref().map_or("live".to_string(), |o| o.short_name());
+        elephant_log::display!("Changes on {} from {} to {to}:", filesystem.name(), snapshot.short_name());
+        for change in changes.iter() {
+            let renamed = change.new_path.as_ref().map_or(String::new(), |n| format!(" -> {n}"));
+            elephant_log::display!("{} {} {:<9} {}{renamed}", change.kind.symbol(),
+                    change.timestamp.format("%Y-%m-%d %H:%M:%S"), change.file_kind.name(), change.path);
+        }
+
+        let count = |kind: ChangeKind| changes.iter().filter(|c| c.kind == kind).count();
+        elephant_log::display!("{} added, {} removed, {} modified, {} renamed",
+                count(ChangeKind::Added), count(ChangeKind::Removed),
+                count(ChangeKind::Modified), count(ChangeKind::Renamed));
+
         Ok(())
     }
 }
@@ -140,23 +868,14 @@ impl SubCommand for ShowCommand {
         for filesystem in filesystems {
             let filesystem = Filesystem::from(filesystem)?;
 
-            let snapshots = filesystem.snapshots();
-            let generation = Snapshot::generation(&snapshots);
-
-            elephant_log::display!("Young snapshots:");
-            for snapshot in generation.young {
-                elephant_log::display!("{}", snapshot.name());
-            }
-
-            elephant_log::display!("Middle snapshots:");
-            for snapshot in generation.middle {
-                elephant_log::display!("{}", snapshot.name());
+            // display only the selected snapshot with the `snapshot` argument.
+            if args.snapshot.is_some() {
+                let snapshot = select_snapshot(&filesystem.name(), &filesystem.snapshots(), "show")?;
+                elephant_log::display!("{} ({})", snapshot.name(), snapshot.datetime().format("%Y-%m-%d %H:%M:%S"));
+                continue;
             }
 
-            elephant_log::display!("Old snapshots:");
-            for snapshot in generation.old {
-                elephant_log::display!("{}", snapshot.name());
-            }
+            show_snapshots(&filesystem, &Retention::original());
         }
 
         Ok(())
@@ -5,7 +5,17 @@
 pub mod filesystem;
 pub mod snapshot;
 pub mod command;
+pub mod relay;
+pub mod mapping;
+pub mod plan;
+pub mod selector;
+pub mod change;
 
 pub use filesystem::Filesystem;
-pub use snapshot::Snapshot;
-pub use command::Driver;
+pub use snapshot::{Snapshot, Retention};
+pub use command::{Driver, SendOption, Transport};
+pub use relay::{Relay, Transfer};
+pub use mapping::Mapping;
+pub use plan::Plan;
+pub use selector::Selector;
+pub use change::{Change, ChangeKind};
@@ -0,0 +1,227 @@
+// Copyright (c) 2022 Patineboot.
+// All rights reserved.
+// Elephant Archive is licensed under BSD 2-Clause License.
+
+use chrono::{Local, DateTime, TimeZone};
+
+/// The kind of a change on a file between two snapshots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The file was created, `+` on `zfs diff`.
+    Added,
+    /// The file was removed, `-` on `zfs diff`.
+    Removed,
+    /// The file was modified, `M` on `zfs diff`.
+    Modified,
+    /// The file was renamed, `R` on `zfs diff`.
+    Renamed,
+}
+
+impl ChangeKind {
+    /// Make the kind of a change from its symbol on `zfs diff`.
+    fn parse(symbol: &str) -> Option<ChangeKind> {
+        match symbol {
+            "+" => Some(ChangeKind::Added),
+            "-" => Some(ChangeKind::Removed),
+            "M" => Some(ChangeKind::Modified),
+            "R" => Some(ChangeKind::Renamed),
+            _ => None,
+        }
+    }
+
+    /// Get the symbol of this kind on `zfs diff`.
+    pub fn symbol(&self) -> char {
+        match self {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Modified => 'M',
+            ChangeKind::Renamed => 'R',
+        }
+    }
+}
+
+/// The type of a changed file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+    BlockDevice,
+    CharacterDevice,
+    Pipe,
+    Socket,
+    Door,
+    EventPort,
+}
+
+impl FileKind {
+    /// Make the type of a file from its indicator on `zfs diff -F`.
+    fn parse(indicator: &str) -> Option<FileKind> {
+        match indicator {
+            "F" => Some(FileKind::File),
+            "/" => Some(FileKind::Directory),
+            "@" => Some(FileKind::Symlink),
+            "B" => Some(FileKind::BlockDevice),
+            "C" => Some(FileKind::CharacterDevice),
+            "|" => Some(FileKind::Pipe),
+            "=" => Some(FileKind::Socket),
+            ">" => Some(FileKind::Door),
+            "P" => Some(FileKind::EventPort),
+            _ => None,
+        }
+    }
+
+    /// Get the name of this type shown on the display.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Directory => "directory",
+            FileKind::Symlink => "symlink",
+            FileKind::BlockDevice => "block device",
+            FileKind::CharacterDevice => "character device",
+            FileKind::Pipe => "pipe",
+            FileKind::Socket => "socket",
+            FileKind::Door => "door",
+            FileKind::EventPort => "event port",
+        }
+    }
+}
+
+/// A change on a file between two snapshots, or a snapshot and the live filesystem.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// The time the file was changed.
+    pub timestamp: DateTime<Local>,
+    /// The kind of the change.
+    pub kind: ChangeKind,
+    /// The type of the file changed.
+    pub file_kind: FileKind,
+    /// The path of the file changed, the old path of a renamed file.
+    pub path: String,
+    /// The new path of a renamed file, or `None` for the other changes.
+    pub new_path: Option<String>,
+}
+
+impl Change {
+    /// Make a change from a line of `zfs diff -FHt`, the tab-separated fields like
+    /// `1668000000.123456789 M F /pool/fs/file` with the new path following a renamed file.
+    pub fn parse(line: &str) -> Result<Change, String> {
+        let malformed = || format!("'{line}' is not a line of 'zfs diff -FHt'");
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let (timestamp, kind, file_kind, path) = match fields[..] {
+            [timestamp, kind, file_kind, path, ..] => (timestamp, kind, file_kind, path),
+            _ => return Err(malformed()),
+        };
+
+        // the fraction of a second has 9 digits of the nanoseconds, a shorter one is padded like a decimal.
+        let (seconds, fraction) = timestamp.split_once('.').unwrap_or((timestamp, "0"));
+        let nanoseconds = Some(fraction)
+                .filter(|f| f.len() <= 9 && f.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|f| format!("{f:0<9}").parse().ok());
+        let timestamp = match (seconds.parse(), nanoseconds) {
+            (Ok(seconds), Some(nanoseconds)) => Local.timestamp_opt(seconds, nanoseconds).single(),
+            _ => None,
+        };
+
+        let kind = ChangeKind::parse(kind).ok_or_else(malformed)?;
+        let new_path = match (kind, fields.get(4)) {
+            (ChangeKind::Renamed, Some(new_path)) => Some(Self::unescape(new_path)),
+            (ChangeKind::Renamed, None) => return Err(malformed()),
+            _ => None,
+        };
+
+        Ok(Change {
+            timestamp: timestamp.ok_or_else(malformed)?,
+            kind,
+            file_kind: FileKind::parse(file_kind).ok_or_else(malformed)?,
+            path: Self::unescape(path),
+            new_path,
+        })
+    }
+
+    /// Decode the path escaped by `zfs diff`, the special characters are like `\0040` in octal.
+    fn unescape(path: &str) -> String {
+        let bytes = path.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+
+        let mut index = 0;
+        while index < bytes.len() {
+            let octal = bytes.get(index + 1..index + 5)
+                    .filter(|_| bytes[index] == b'\\')
+                    .and_then(|digits| std::str::from_utf8(digits).ok())
+                    .and_then(|digits| digits.strip_prefix('0'))
+                    .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+            match octal {
+                Some(byte) => {
+                    decoded.push(byte);
+                    index += 5;
+                },
+                None => {
+                    decoded.push(bytes[index]);
+                    index += 1;
+                },
+            }
+        }
+
+        String::from_utf8_lossy(&decoded).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescape_octal() {
+        assert_eq!(Change::unescape("/pool/fs/new\\0040file"), "/pool/fs/new file");
+        assert_eq!(Change::unescape("/pool/fs/tab\\0011and\\0134slash"), "/pool/fs/tab\tand\\slash");
+        assert_eq!(Change::unescape("/pool/fs/caf\\0303\\0251"), "/pool/fs/café");
+        // not an escape sequence, kept as it is.
+        assert_eq!(Change::unescape("/pool/fs/a\\09b"), "/pool/fs/a\\09b");
+        assert_eq!(Change::unescape("/pool/fs/end\\00"), "/pool/fs/end\\00");
+    }
+
+    #[test]
+    fn parse_modified_line() {
+        let change = Change::parse("1668000000.123456789\tM\t/\t/pool/fs/docs").unwrap();
+
+        assert_eq!(change.timestamp, Local.timestamp_opt(1668000000, 123456789).unwrap());
+        assert_eq!(change.kind, ChangeKind::Modified);
+        assert_eq!(change.file_kind, FileKind::Directory);
+        assert_eq!(change.path, "/pool/fs/docs");
+        assert_eq!(change.new_path, None);
+    }
+
+    #[test]
+    fn parse_renamed_line() {
+        let change = Change::parse("1668000000.500000000\tR\tF\t/pool/fs/old\\0040name\t/pool/fs/new\\0040name").unwrap();
+
+        assert_eq!(change.timestamp, Local.timestamp_opt(1668000000, 500000000).unwrap());
+        assert_eq!(change.kind, ChangeKind::Renamed);
+        assert_eq!(change.file_kind, FileKind::File);
+        assert_eq!(change.path, "/pool/fs/old name");
+        assert_eq!(change.new_path, Some("/pool/fs/new name".to_string()));
+    }
+
+    #[test]
+    fn parse_short_fraction() {
+        let half = Change::parse("1668000000.5\t+\tF\t/pool/fs/file").unwrap();
+        let whole = Change::parse("1668000000\t+\tF\t/pool/fs/file").unwrap();
+
+        assert_eq!(half.timestamp, Local.timestamp_opt(1668000000, 500000000).unwrap());
+        assert_eq!(whole.timestamp, Local.timestamp_opt(1668000000, 0).unwrap());
+    }
+
+    #[test]
+    fn parse_malformed_lines() {
+        // a renamed file without the new path.
+        assert!(Change::parse("1668000000.500000000\tR\tF\t/pool/fs/old").is_err());
+        assert!(Change::parse("1668000000.500000000\tX\tF\t/pool/fs/file").is_err());
+        assert!(Change::parse("1668000000.500000000\t+\tZ\t/pool/fs/file").is_err());
+        assert!(Change::parse("yesterday\t+\tF\t/pool/fs/file").is_err());
+        assert!(Change::parse("1668000000.1234567890\t+\tF\t/pool/fs/file").is_err());
+        assert!(Change::parse("1668000000.-5\t+\tF\t/pool/fs/file").is_err());
+        assert!(Change::parse("1668000000.500000000\t+\tF").is_err());
+    }
+}
@@ -2,56 +2,323 @@
 // All rights reserved.
 // Elephant Archive is licensed under BSD 2-Clause License.
 
-use std::process::{Command, Stdio};
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::{Command, Stdio, Child, ChildStdin, ChildStdout, Output};
+use std::thread;
+use once_cell::sync::OnceCell;
 
-pub struct Driver;
+use super::relay::{Relay, Transfer, Progress};
 
-static DRIVER_INSTANCE: Driver = Driver;
+/// A transport carrying the command lines of a driver to the machine running them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transport {
+    /// Run the command lines on this machine.
+    Local,
+    /// Run the command lines through a command wrapper like `ssh backup-host`.
+    Wrapper(String),
+}
+
+impl Transport {
+    /// Make a transport from a command wrapper, or the local transport from `None`.
+    pub fn from(wrapper: Option<&str>) -> Transport {
+        match wrapper {
+            Some(wrapper) if !wrapper.trim().is_empty() => Transport::Wrapper(wrapper.to_string()),
+            _ => Transport::Local,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Driver {
+    transport: Transport,
+}
+
+static DRIVER_INSTANCE: Driver = Driver { transport: Transport::Local };
+
+static ARCHIVE_DRIVER_INSTANCE: OnceCell<Driver> = OnceCell::new();
+
+/// Options for sending a stream with `zfs send`.
+#[derive(Debug, Clone, Default)]
+pub struct SendOption {
+    /// Send the encrypted blocks as they are on the disk without loading the keys.
+    pub raw: bool,
+    /// Show the progress of the stream with the size estimated before sending.
+    pub progress: bool,
+    /// The other streams are sent in parallel, their progresses are logged in lines not redrawn on a line.
+    pub parallel: bool,
+    /// The maximum bytes per second of the stream, or `None` for no limit.
+    pub rate_limit: Option<u64>,
+    /// The bytes of the buffer between `zfs send` and the receiver, or `None` for no buffer.
+    pub buffer: Option<u64>,
+    /// Send the descendant filesystems with their snapshots and properties.
+    pub recursive: bool,
+    /// Send the locally set properties of the filesystem.
+    pub properties: bool,
+    /// The properties overridden on receiving like `mountpoint=none`.
+    pub overrides: Vec<String>,
+    /// The properties excluded from the stream on receiving like `mountpoint`.
+    pub excludes: Vec<String>,
+    /// Roll back the receiving filesystem to the base of the stream,
+    /// destroying the snapshots and the changes after the base.
+    pub rollback: bool,
+}
+
+impl SendOption {
+    /// Get the options on the command line of `zfs send`.
+    fn arguments(&self) -> String {
+        let mut arguments = Vec::new();
+        if self.raw {
+            arguments.push("-w");
+        }
+        if self.recursive {
+            arguments.push("-R");
+        }
+        if self.properties {
+            arguments.push("-p");
+        }
+
+        arguments.join(" ")
+    }
+
+    /// Get the options on the command line of `zfs receive`.
+    /// A replication stream of descendant filesystems cannot be resumed.
+    fn receive_arguments(&self) -> String {
+        let mut arguments = Vec::new();
+        if !self.recursive {
+            arguments.push("-s".to_string());
+        }
+        if self.rollback {
+            arguments.push("-F".to_string());
+        }
+        for property in self.overrides.iter() {
+            arguments.push(format!("-o {property}"));
+        }
+        for property in self.excludes.iter() {
+            arguments.push(format!("-x {property}"));
+        }
+
+        arguments.join(" ")
+    }
+}
 
 /// Command Line: show ZFS filesystems on this machine.
 const ZFS_LIST_FILESYSTEM: &str = "zfs list -H -o name -t filesystem";
 
+/// Command Line: show ZFS filesystems with their mountpoints on this machine.
+const ZFS_LIST_MOUNTPOINT: &str = "zfs list -H -o name,mountpoint -t filesystem";
+
 /// Command Line: show snapshots on this machine.
 const ZFS_LIST_SNAPSHOT: &str = "zfs list -H -s creation -o name -t snapshot";
 
-/// Command Line: take a snapshot recursively on a ZFS filesystem.
-const ZFS_TAKE_SNAPSHOT: &str = "zfs snapshot -r";
+/// Command Line: show snapshots of a ZFS filesystem.
+const ZFS_LIST_SNAPSHOT_OF: &str = "zfs list -H -s creation -o name -t snapshot -d 1";
+
+/// Command Line: create a ZFS filesystem with its missing parents, unmountable.
+const ZFS_CREATE_PARENT: &str = "zfs create -p -o canmount=off";
+
+/// Command Line: show bookmarks of a ZFS filesystem.
+const ZFS_LIST_BOOKMARK_OF: &str = "zfs list -H -s creation -o name -t bookmark -d 1";
+
+/// Command Line: create a bookmark of a snapshot.
+const ZFS_BOOKMARK: &str = "zfs bookmark";
+
+/// Command Line: destroy a bookmark.
+const ZFS_DESTROY_BOOKMARK: &str = "zfs destroy";
+
+/// Command Line: show a property value of a ZFS dataset.
+const ZFS_GET_PROPERTY: &str = "zfs get -H -p -o value";
+
+/// Command Line: show a property value set locally on a ZFS dataset, not inherited from its parent.
+const ZFS_GET_LOCAL_PROPERTY: &str = "zfs get -H -p -o value -s local";
+
+/// Command Line: show a property value of all of the ZFS filesystems with their names.
+const ZFS_GET_PROPERTY_ALL: &str = "zfs get -H -p -o name,value -t filesystem";
+
+/// Command Line: set a property value on a ZFS dataset.
+const ZFS_SET_PROPERTY: &str = "zfs set";
+
+/// Command Line: clone a snapshot to a writable ZFS filesystem.
+const ZFS_CLONE: &str = "zfs clone";
+
+/// Command Line: destroy a ZFS filesystem with its snapshots.
+const ZFS_DESTROY_FILESYSTEM: &str = "zfs destroy -r";
+
+/// Command Line: rename a ZFS filesystem.
+const ZFS_RENAME: &str = "zfs rename";
+
+/// Command Line: take a snapshot on a ZFS filesystem.
+const ZFS_TAKE_SNAPSHOT: &str = "zfs snapshot";
 
 /// Command Line: destroy a snapshot recursively on a ZFS filesystem.
 const ZFS_DESTROY_SNAPSHOT: &str = "zfs destroy -r";
 
+/// Command Line: roll a ZFS filesystem back to a snapshot, destroying the snapshots after it.
+const ZFS_ROLLBACK: &str = "zfs rollback -r";
+
+/// Command Line: show the changes on the files between a snapshot and another snapshot or the filesystem.
+const ZFS_DIFF: &str = "zfs diff -FHt";
+
+/// Command Line: hold a snapshot with a tag not to be destroyed.
+const ZFS_HOLD: &str = "zfs hold";
+
+/// Command Line: release a hold with a tag from a snapshot.
+const ZFS_RELEASE: &str = "zfs release";
+
+/// Command Line: show the holds on snapshots.
+const ZFS_HOLDS: &str = "zfs holds -H";
+
+/// Command Line: send a snapshot as a stream to the standard output.
+const ZFS_SEND: &str = "zfs send";
+
+/// Command Line: estimate the size of a stream without sending.
+const ZFS_SEND_DRYRUN: &str = "zfs send -n -v -P";
+
+/// Command Line: receive a stream from the standard input into a ZFS filesystem.
+/// The received filesystem is not mounted, the other options like saving the receive state follow `SendOption`.
+const ZFS_RECEIVE: &str = "zfs receive -u";
+
+/// Command Line: abort an interrupted receive and discard its state.
+const ZFS_RECEIVE_ABORT: &str = "zfs receive -A";
+
 
 impl Driver {
     pub fn get_instance() -> &'static Driver {
         &DRIVER_INSTANCE
     }
 
+    /// Set up the driver for the archive with `transport`.
+    /// `init_archive` function must be called before `get_archive_instance` function.
+    pub fn init_archive(transport: Transport) {
+        if transport == Transport::Local {
+            return;
+        }
+
+        ARCHIVE_DRIVER_INSTANCE.set(Driver { transport }).unwrap();
+    }
+
+    /// Get the driver running the command lines for the archive.
+    /// The driver is the same as `get_instance` function unless a transport is set up.
+    pub fn get_archive_instance() -> &'static Driver {
+        ARCHIVE_DRIVER_INSTANCE.get().unwrap_or(&DRIVER_INSTANCE)
+    }
+
+    /// Confirm the driver runs the command lines on this machine or not.
+    pub fn is_local(&self) -> bool {
+        self.transport == Transport::Local
+    }
+
     /// Get all of the filesystems on this machine.
     pub fn get_filesystems(&self) -> Vec<String> {
-        let cl = format!("{ZFS_LIST_FILESYSTEM}");
-        let stdout = self.spawn(&cl);
+        let stdout = self.spawn(ZFS_LIST_FILESYSTEM);
 
         let lines = stdout.lines();
-        let filesystems = lines.map(|s| s.to_string()).collect();
+        lines.map(|s| s.to_string()).collect()
+    }
+
+    /// Get the ZFS filesystems with their mountpoints as pairs of a filesystem and a mountpoint.
+    pub fn get_mountpoints(&self) -> Vec<(String, String)> {
+        let stdout = self.spawn(ZFS_LIST_MOUNTPOINT);
 
-        filesystems
+        stdout.lines()
+                .filter_map(|line| line.split_once('\t'))
+                .map(|(name, mountpoint)| (name.to_string(), mountpoint.to_string()))
+                .collect()
     }
 
     /// Get all of the snapshots on this machine.
     pub fn get_snapshots(&self) -> Vec<String> {
-        let cl = format!("{ZFS_LIST_SNAPSHOT}");
+        let stdout = self.spawn(ZFS_LIST_SNAPSHOT);
+
+        let lines = stdout.lines();
+        lines.map(|s| s.to_string()).collect()
+    }
+
+    /// Get the snapshots of the `filesystem` filesystem.
+    pub fn get_snapshots_of(&self, filesystem: &str) -> Vec<String> {
+        let cl = format!("{ZFS_LIST_SNAPSHOT_OF} {filesystem}");
         let stdout = self.spawn(&cl);
 
         let lines = stdout.lines();
-        let snapshots = lines.map(|s| s.to_string()).collect();
+        lines.map(|s| s.to_string()).collect()
+    }
+
+    /// Get the value of `property` on the `dataset` dataset.
+    pub fn get_property(&self, dataset: &str, property: &str) -> String {
+        let cl = format!("{ZFS_GET_PROPERTY} {property} {dataset}");
+        let stdout = self.spawn(&cl);
+
+        stdout.trim().to_string()
+    }
+
+    /// Get the value of `property` set locally on the `dataset` dataset, or an empty string if it is not set.
+    pub fn get_local_property(&self, dataset: &str, property: &str) -> String {
+        let cl = format!("{ZFS_GET_LOCAL_PROPERTY} {property} {dataset}");
+        let stdout = self.spawn(&cl);
+
+        stdout.trim().to_string()
+    }
 
-        snapshots
+    /// Set `value` to `property` on the `dataset` dataset.
+    /// `set_property` function must be called by the root user.
+    pub fn set_property(&self, dataset: &str, property: &str, value: &str) -> Result<(), String> {
+        let cl = format!("{ZFS_SET_PROPERTY} {property}={value} {dataset}");
+        self.execute(&cl)?;
+
+        Ok(())
     }
 
-    /// Take the snapshot named with `snapshot`.
+    /// Get the values of `property` on all of the ZFS filesystems as pairs of a filesystem and a value.
+    pub fn get_property_all(&self, property: &str) -> Vec<(String, String)> {
+        let cl = format!("{ZFS_GET_PROPERTY_ALL} {property}");
+        let stdout = self.spawn(&cl);
+
+        stdout.lines()
+                .filter_map(|line| line.split_once('\t'))
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+    }
+
+    /// Create the `filesystem` filesystem with its missing parents.
+    /// The created filesystem is never mounted.
+    pub fn create_filesystem(&self, filesystem: &str) -> Result<(), String> {
+        let cl = format!("{ZFS_CREATE_PARENT} {filesystem}");
+        self.execute(&cl)?;
+
+        Ok(())
+    }
+
+    /// Clone `snapshot` to the `filesystem` filesystem with `properties` as pairs of a property and a value.
+    pub fn clone_snapshot(&self, snapshot: &str, filesystem: &str, properties: &[(String, String)])
+            -> Result<(), String> {
+        let options: Vec<String> = properties.iter().map(|(k, v)| format!("-o {k}={v}")).collect();
+        let cl = format!("{ZFS_CLONE} {} {snapshot} {filesystem}", options.join(" "));
+        self.execute(&cl)?;
+
+        Ok(())
+    }
+
+    /// Destroy the `filesystem` filesystem with its snapshots.
+    pub fn destroy_filesystem(&self, filesystem: &str) -> Result<(), String> {
+        let cl = format!("{ZFS_DESTROY_FILESYSTEM} {filesystem}");
+        self.execute(&cl)?;
+
+        Ok(())
+    }
+
+    /// Rename the `filesystem` filesystem to `name`.
+    pub fn rename(&self, filesystem: &str, name: &str) -> Result<(), String> {
+        let cl = format!("{ZFS_RENAME} {filesystem} {name}");
+        self.execute(&cl)?;
+
+        Ok(())
+    }
+
+    /// Take the snapshot named with `snapshot`, on the descendant filesystems too if `recursive` is true.
     /// `take_snapshot` function must be called by the root user.
-    pub fn take_snapshot(&self, snapshot: &str) {
-        let cl = format!("{ZFS_TAKE_SNAPSHOT} {snapshot}");
+    pub fn take_snapshot(&self, snapshot: &str, recursive: bool) {
+        let recursive = if recursive { " -r" } else { "" };
+        let cl = format!("{ZFS_TAKE_SNAPSHOT}{recursive} {snapshot}");
         self.spawn(&cl);
     }
 
@@ -62,10 +329,432 @@ impl Driver {
         self.spawn(&cl);
     }
 
+    /// Get the bookmarks of the `filesystem` filesystem in the order of their creation.
+    pub fn get_bookmarks_of(&self, filesystem: &str) -> Vec<String> {
+        let cl = format!("{ZFS_LIST_BOOKMARK_OF} {filesystem}");
+        let stdout = self.spawn(&cl);
+
+        stdout.lines().map(|s| s.to_string()).collect()
+    }
+
+    /// Create `bookmark` marking `snapshot`.
+    /// A bookmark can be the base of an incremental stream after the snapshot is destroyed.
+    pub fn create_bookmark(&self, snapshot: &str, bookmark: &str) -> Result<(), String> {
+        let cl = format!("{ZFS_BOOKMARK} {snapshot} {bookmark}");
+        self.execute(&cl)?;
+
+        Ok(())
+    }
+
+    /// Destroy `bookmark`.
+    pub fn destroy_bookmark(&self, bookmark: &str) -> Result<(), String> {
+        let cl = format!("{ZFS_DESTROY_BOOKMARK} {bookmark}");
+        self.execute(&cl)?;
+
+        Ok(())
+    }
+
+    /// Get the clones depending on `snapshot`.
+    pub fn get_clones(&self, snapshot: &str) -> Vec<String> {
+        let clones = self.get_property(snapshot, "clones");
+
+        clones.split(',')
+                .filter(|c| !c.is_empty() && *c != "-")
+                .map(|c| c.to_string())
+                .collect()
+    }
+
+    /// Roll the filesystem of `snapshot` back to it, destroying the snapshots and the bookmarks after it.
+    /// The clones of the destroyed snapshots are destroyed only if `destroy_clones` is true.
+    pub fn rollback(&self, snapshot: &str, destroy_clones: bool) -> Result<(), String> {
+        let clones = if destroy_clones { " -R" } else { "" };
+        let cl = format!("{ZFS_ROLLBACK}{clones} {snapshot}");
+        self.execute(&cl)?;
+
+        Ok(())
+    }
+
+    /// Get the lines of the changes on the files from `snapshot` to `other`,
+    /// a later snapshot or the filesystem of `snapshot`.
+    pub fn diff(&self, snapshot: &str, other: &str) -> Result<Vec<String>, String> {
+        let cl = format!("{ZFS_DIFF} {snapshot} {other}");
+        let stdout = self.execute(&cl)?;
+
+        Ok(stdout.lines().map(|s| s.to_string()).collect())
+    }
+
+    /// Hold `snapshot` with `tag`, the snapshot cannot be destroyed until the hold is released.
+    /// The snapshots of the same name on the descendant filesystems are held together if `recursive` is true.
+    pub fn hold(&self, snapshot: &str, tag: &str, recursive: bool) -> Result<(), String> {
+        let recursive = if recursive { " -r" } else { "" };
+        let cl = format!("{ZFS_HOLD}{recursive} {tag} {snapshot}");
+        self.execute(&cl)?;
+
+        Ok(())
+    }
+
+    /// Release the hold with `tag` from `snapshot`.
+    /// The holds on the snapshots of the same name on the descendant filesystems are released together
+    /// if `recursive` is true.
+    pub fn release(&self, snapshot: &str, tag: &str, recursive: bool) -> Result<(), String> {
+        let recursive = if recursive { " -r" } else { "" };
+        let cl = format!("{ZFS_RELEASE}{recursive} {tag} {snapshot}");
+        self.execute(&cl)?;
+
+        Ok(())
+    }
+
+    /// Get the holds on `snapshots` as pairs of a snapshot and a tag.
+    pub fn holds(&self, snapshots: &[String]) -> Vec<(String, String)> {
+        if snapshots.is_empty() {
+            return Vec::new();
+        }
+
+        let cl = format!("{ZFS_HOLDS} {}", snapshots.join(" "));
+        let stdout = self.spawn(&cl);
+
+        // the line of a hold is like "pool/fs@snapshot	tag	timestamp".
+        stdout.lines()
+                .filter_map(|line| {
+                    let mut fields = line.split('\t');
+                    Some((fields.next()?.to_string(), fields.next()?.to_string()))
+                })
+                .collect()
+    }
+
+    /// Send the whole of `snapshot` and receive it as the `filesystem` filesystem on `receiver`.
+    /// `send_receive` function must be called by the root user.
+    pub fn send_receive(&self, snapshot: &str, filesystem: &str, option: &SendOption,
+            receiver: &Driver) -> Result<Transfer, String> {
+        let arguments = format!("{} {snapshot}", option.arguments());
+        let relay = self.relay(option, &arguments, snapshot);
+        let receive = format!("{ZFS_RECEIVE} {} {filesystem}", option.receive_arguments());
+        self.pipe(&format!("{ZFS_SEND} {arguments}"), receiver, &receive, relay)
+    }
+
+    /// Send the differences from `base` to `snapshot` with all of the intermediary snapshots,
+    /// and receive them on the `filesystem` filesystem on `receiver`.
+    /// `send_receive_incremental` function must be called by the root user.
+    pub fn send_receive_incremental(&self, base: &str, snapshot: &str, filesystem: &str,
+            option: &SendOption, receiver: &Driver) -> Result<Transfer, String> {
+        let arguments = format!("{} -I {base} {snapshot}", option.arguments());
+        let relay = self.relay(option, &arguments, snapshot);
+        let receive = format!("{ZFS_RECEIVE} {} {filesystem}", option.receive_arguments());
+        self.pipe(&format!("{ZFS_SEND} {arguments}"), receiver, &receive, relay)
+    }
+
+    /// Send only the differences from `base` to `snapshot` without the intermediary snapshots,
+    /// and receive them on the `filesystem` filesystem on `receiver`.
+    /// `base` can be a bookmark.
+    /// `send_receive_from` function must be called by the root user.
+    pub fn send_receive_from(&self, base: &str, snapshot: &str, filesystem: &str,
+            option: &SendOption, receiver: &Driver) -> Result<Transfer, String> {
+        let arguments = format!("{} -i {base} {snapshot}", option.arguments());
+        let relay = self.relay(option, &arguments, snapshot);
+        let receive = format!("{ZFS_RECEIVE} {} {filesystem}", option.receive_arguments());
+        self.pipe(&format!("{ZFS_SEND} {arguments}"), receiver, &receive, relay)
+    }
+
+    /// Send `snapshot` to the `path` file, only the differences from `base` if any.
+    /// The stream passes through the `filters` command lines in order before written to `path`,
+    /// like `zfs send | filter1 | filter2 > path` on a shell.
+    /// Return the number of bytes of the stream sent and the checksum of the `path` file.
+    /// `send_file` function must be called by the root user.
+    pub fn send_file(&self, snapshot: &str, base: Option<&str>, option: &SendOption,
+            filters: &[String], path: &Path) -> Result<Transfer, String> {
+        let arguments = match base {
+            Some(base) => format!("{} -i {base} {snapshot}", option.arguments()),
+            None => format!("{} {snapshot}", option.arguments()),
+        };
+        let mut relay = self.relay(option, &arguments, snapshot);
+        let send = format!("{ZFS_SEND} {arguments}");
+
+        let mut file = File::create(path)
+                .map_err(|e| format!("Failed to create '{}': {e}", path.display()))?;
+
+        // connect the filters to each other.
+        let mut filter_children: Vec<Child> = Vec::new();
+        let mut first_stdin: Option<ChildStdin> = None;
+        let mut previous_stdout: Option<ChildStdout> = None;
+        for (index, filter) in filters.iter().enumerate() {
+            let stdin = match previous_stdout.take() {
+                Some(stdout) => Stdio::from(stdout),
+                None => Stdio::piped(),
+            };
+
+            let mut child = self.start(filter, stdin, Stdio::piped())?;
+            if index == 0 {
+                first_stdin = child.stdin.take();
+            }
+            previous_stdout = child.stdout.take();
+            filter_children.push(child);
+        }
+
+        let mut send_child = self.start(&send, Stdio::null(), Stdio::piped())?;
+        let mut send_stdout = send_child.stdout.take().unwrap();
+
+        let (sent, written) = match (first_stdin, previous_stdout) {
+            (Some(mut first_stdin), Some(mut last_stdout)) => {
+                // write the output of the last filter to the file on another thread.
+                let writer = thread::spawn(move || {
+                    let mut relay = Relay::new();
+                    relay.copy(&mut last_stdout, &mut file)
+                            .map(|_| relay.finish())
+                            .map_err(|e| e.to_string())
+                });
+
+                let relayed = relay.copy(&mut send_stdout, &mut first_stdin);
+                drop(send_stdout);
+                // close the standard input of the first filter to finish the filters.
+                drop(first_stdin);
+
+                let written = writer.join().unwrap();
+                (relayed.map(|_| relay.finish()).map_err(|e| e.to_string()), written)
+            },
+            _ => {
+                let relayed = relay.copy(&mut send_stdout, &mut file);
+                drop(send_stdout);
+
+                // the stream is written to the file as it is.
+                let written = relayed.map(|_| relay.finish()).map_err(|e| e.to_string());
+                (written.clone(), written)
+            },
+        };
+
+        let send_output = Self::finish(send_child)?;
+        Self::check(&send, &send_output)?;
+        for (filter, child) in filters.iter().zip(filter_children) {
+            let output = Self::finish(child)?;
+            Self::check(filter, &output)?;
+        }
+
+        let sent = sent.map_err(|e| format!("Failed to relay '{send}': {e}"))?;
+        let written = written.map_err(|e| format!("Failed to write '{}': {e}", path.display()))?;
+        File::open(path).and_then(|f| f.sync_all())
+                .map_err(|e| format!("Failed to write '{}': {e}", path.display()))?;
+
+        Ok(Transfer {
+            bytes: sent.bytes,
+            sha256: written.sha256,
+        })
+    }
+
+    /// Receive the stream in the `path` file as the `filesystem` filesystem.
+    /// The stream passes through the `filters` command lines before received, like a decompressor.
+    /// `receive_file` function must be called by the root user.
+    pub fn receive_file(&self, path: &Path, filters: &[String], filesystem: &str,
+            option: &SendOption) -> Result<Transfer, String> {
+        let mut file = File::open(path)
+                .map_err(|e| format!("Failed to open '{}': {e}", path.display()))?;
+        let receive = format!("{ZFS_RECEIVE} {} {filesystem}", option.receive_arguments());
+
+        // connect the filters and the receiver to each other.
+        let command_lines: Vec<&str> = filters.iter().map(|f| f.as_str())
+                .chain([receive.as_str()])
+                .collect();
+        let mut children: Vec<Child> = Vec::new();
+        let mut first_stdin: Option<ChildStdin> = None;
+        let mut previous_stdout: Option<ChildStdout> = None;
+        for (index, command_line) in command_lines.iter().enumerate() {
+            let stdin = match previous_stdout.take() {
+                Some(stdout) => Stdio::from(stdout),
+                None => Stdio::piped(),
+            };
+
+            let mut child = self.start(command_line, stdin, Stdio::piped())?;
+            if index == 0 {
+                first_stdin = child.stdin.take();
+            }
+            // the output of the receiver is kept to report its errors.
+            if index + 1 < command_lines.len() {
+                previous_stdout = child.stdout.take();
+            }
+            children.push(child);
+        }
+
+        let mut first_stdin = first_stdin.unwrap();
+        let progress = option.progress
+                .then(|| Progress::new(filesystem, fs::metadata(path).map(|m| m.len()).ok(), option.parallel));
+        let mut relay = Relay::with(progress, option.rate_limit, option.buffer);
+        let relayed = relay.copy(&mut file, &mut first_stdin);
+        // close the standard input of the first command to finish the commands.
+        drop(first_stdin);
+
+        for (command_line, child) in command_lines.iter().zip(children) {
+            let output = Self::finish(child)?;
+            Self::check(command_line, &output)?;
+        }
+
+        let read = relayed.map(|_| relay.finish())
+                .map_err(|e| format!("Failed to read '{}': {e}", path.display()))?;
+        Ok(read)
+    }
+
+    /// Resume the interrupted stream with `token` and receive it on the `filesystem` filesystem
+    /// on `receiver`.
+    /// `resume_send_receive` function must be called by the root user.
+    pub fn resume_send_receive(&self, token: &str, filesystem: &str, option: &SendOption,
+            receiver: &Driver) -> Result<Transfer, String> {
+        let arguments = format!("-t {token}");
+        let relay = self.relay(option, &arguments, filesystem);
+        let receive = format!("{ZFS_RECEIVE} {} {filesystem}", option.receive_arguments());
+        self.pipe(&format!("{ZFS_SEND} {arguments}"), receiver, &receive, relay)
+    }
+
+    /// Confirm the interrupted stream can be resumed with `token`.
+    /// The stream cannot be resumed when its snapshot has been destroyed on the sender.
+    pub fn check_resume(&self, token: &str) -> Result<(), String> {
+        let cl = format!("{ZFS_SEND_DRYRUN} -t {token}");
+        self.execute(&cl)?;
+
+        Ok(())
+    }
+
+    /// Estimate the number of bytes of the stream sent with `arguments` of `zfs send`.
+    /// Return `None` if the size cannot be estimated.
+    fn estimate(&self, arguments: &str) -> Option<u64> {
+        let cl = format!("{ZFS_SEND_DRYRUN} {arguments}");
+        let stdout = self.execute(&cl).ok()?;
+
+        // the line of the total size is like "size\t1234".
+        let size = stdout.lines()
+                .filter_map(|line| line.strip_prefix("size\t"))
+                .next_back()?;
+        size.trim().parse().ok()
+    }
+
+    /// Estimate the bytes of every snapshot in the stream of `snapshot`,
+    /// the differences from `base` if any, with the intermediary snapshots if `intermediary`.
+    /// Return pairs of a snapshot and its estimated bytes in the order they are sent.
+    pub fn estimate_streams(&self, base: Option<&str>, snapshot: &str, intermediary: bool,
+            option: &SendOption) -> Result<Vec<(String, u64)>, String> {
+        let flag = if intermediary { "-I" } else { "-i" };
+        let arguments = match base {
+            Some(base) => format!("{} {flag} {base} {snapshot}", option.arguments()),
+            None => format!("{} {snapshot}", option.arguments()),
+        };
+        let cl = format!("{ZFS_SEND_DRYRUN} {arguments}");
+        let stdout = self.execute(&cl)?;
+
+        // the lines of the snapshots are like "full\tpool/fs@snap\t1234"
+        // or "incremental\tpool/fs@from\tpool/fs@to\t1234".
+        let streams = stdout.lines()
+                .filter(|line| line.starts_with("full\t") || line.starts_with("incremental\t"))
+                .filter_map(|line| {
+                    let mut fields = line.split('\t').rev();
+                    let size = fields.next()?.trim().parse().ok()?;
+                    Some((fields.next()?.to_string(), size))
+                })
+                .collect();
+
+        Ok(streams)
+    }
+
+    /// Make the relay of the `label` stream sent with `arguments` as `option` requires.
+    fn relay(&self, option: &SendOption, arguments: &str, label: &str) -> Relay {
+        let progress = if option.progress {
+            let estimate = self.estimate(arguments);
+            Some(Progress::new(label, estimate, option.parallel))
+        } else {
+            None
+        };
+
+        Relay::with(progress, option.rate_limit, option.buffer)
+    }
+
+    /// Abort the interrupted receive on the `filesystem` filesystem.
+    /// `abort_receive` function must be called by the root user.
+    pub fn abort_receive(&self, filesystem: &str) -> Result<(), String> {
+        let cl = format!("{ZFS_RECEIVE_ABORT} {filesystem}");
+        self.execute(&cl)?;
+
+        Ok(())
+    }
+
     /// Execute a command line involving a program and arguments.
     /// `command_line` is a command line with a program followed 
     /// by arguments separated with whitespace.
     fn spawn(&self, command_line: &str) -> String {
+        match self.execute(command_line) {
+            Ok(stdout) => stdout,
+            Err(message) => panic!("{message}"),
+        }
+    }
+
+    /// Execute a command line like `spawn`, but return an error message on a failure.
+    fn execute(&self, command_line: &str) -> Result<String, String> {
+        let child = self.start(command_line, Stdio::null(), Stdio::piped())?;
+        let output = Self::finish(child)?;
+        Self::check(command_line, &output)?;
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        elephant_log::debug!("stdout: {stdout}");
+        Ok(stdout)
+    }
+
+    /// Execute two command lines connecting the standard output of `sender`
+    /// to the standard input of `receiver`, like `sender | receiver` on a shell.
+    /// `receiver` runs through the transport of `receive_driver`.
+    /// The stream is passed through `relay` on this process to count the bytes and compute the checksum.
+    fn pipe(&self, sender: &str, receive_driver: &Driver, receiver: &str,
+            mut relay: Relay) -> Result<Transfer, String> {
+        let mut send_child = self.start(sender, Stdio::null(), Stdio::piped())?;
+        let mut send_stdout = send_child.stdout.take().unwrap();
+
+        let mut receive_child = match receive_driver.start(receiver, Stdio::piped(), Stdio::null()) {
+            Ok(child) => child,
+            Err(message) => {
+                // do not leave the sender orphaned.
+                let _ = send_child.kill();
+                let _ = send_child.wait();
+                return Err(message);
+            }
+        };
+        let mut receive_stdin = receive_child.stdin.take().unwrap();
+
+        let relayed = relay.copy(&mut send_stdout, &mut receive_stdin);
+        drop(send_stdout);
+        drop(receive_stdin);
+
+        let receive_output = Self::finish(receive_child)?;
+        let send_output = Self::finish(send_child)?;
+
+        if relayed.is_err() {
+            // the receiver stopped reading the stream.
+            Self::check(receiver, &receive_output)?;
+            Self::check(sender, &send_output)?;
+        }
+        else {
+            // the failure of the sender is the cause of the failure of the receiver.
+            Self::check(sender, &send_output)?;
+            Self::check(receiver, &receive_output)?;
+        }
+        relayed.map_err(|e| format!("Failed to relay '{sender}' to '{receiver}': {e}"))?;
+
+        Ok(relay.finish())
+    }
+
+    /// Start a command line with `stdin` and `stdout`, and return the child process.
+    fn start(&self, command_line: &str, stdin: Stdio, stdout: Stdio) -> Result<Child, String> {
+        let mut command = self.command(command_line, stdin, stdout);
+        command.spawn()
+                .map_err(|e| format!("Failed to execute '{command_line}': {e}"))
+    }
+
+    /// Wait for `child` to exit and collect its output.
+    fn finish(child: Child) -> Result<Output, String> {
+        child.wait_with_output()
+                .map_err(|e| format!("Failed to wait on child: {e}"))
+    }
+
+    /// Build a command from a command line.
+    /// The command line is prefixed with the command wrapper of the transport.
+    fn command(&self, command_line: &str, stdin: Stdio, stdout: Stdio) -> Command {
+        let command_line = match &self.transport {
+            Transport::Local => command_line.to_string(),
+            Transport::Wrapper(wrapper) => format!("{wrapper} {command_line}"),
+        };
         elephant_log::info!("spawn: {command_line}");
 
         let mut split = command_line.split_whitespace();
@@ -77,28 +766,28 @@ impl Driver {
 
         let mut command = Command::new(program);
         command.args(arguments);
-        command.stdin(Stdio::null());
+        command.stdin(stdin);
         command.stderr(Stdio::piped());
-        command.stdout(Stdio::piped());
-
-        let child = command.spawn().expect("Failed to execute child");
-        let output = child.wait_with_output().expect("Failed to wait on child");
-        if !output.status.success() {
-            let exit = match output.status.code() {
-                Some(code) => format!("Exited with status code: {code}"),
-                None            => format!("Process terminated by signal"),
-            };
-            let stderr = String::from_utf8(output.stderr).unwrap();
+        command.stdout(stdout);
+
+        command
+    }
 
-            elephant_log::error!("{exit}, See more details =====>");
-            elephant_log::error!("Command Line: '{command_line}', stderr is on the next line:\n{stderr}");
-            panic!();
+    /// Check the exit status of a command line and log its details on a failure.
+    fn check(command_line: &str, output: &Output) -> Result<(), String> {
+        if output.status.success() {
+            return Ok(());
         }
 
+        let exit = match output.status.code() {
+            Some(code) => format!("Exited with status code: {code}"),
+            None => "Process terminated by signal".to_string(),
+        };
+        let stderr = String::from_utf8_lossy(&output.stderr);
 
-        let stdout = String::from_utf8(output.stdout).unwrap();
+        elephant_log::error!("{exit}, See more details =====>");
+        elephant_log::error!("Command Line: '{command_line}', stderr is on the next line:\n{stderr}");
 
-        elephant_log::debug!("stdout: {stdout}");
-        stdout
+        Err(format!("'{command_line}' failed: {}", stderr.trim()))
     }
 }
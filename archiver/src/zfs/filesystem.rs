@@ -2,13 +2,40 @@
 // All rights reserved.
 // Elephant Archive is licensed under BSD 2-Clause License.
 
-use std::{sync::Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
 use once_cell::sync::Lazy;
-use chrono::{Local, Duration, TimeZone};
+use chrono::{Local, DateTime, Duration, TimeZone};
+use sha2::{Sha256, Digest};
 
-use crate::configure;
+use super::{Snapshot, Retention, Driver, SendOption, Transfer, Mapping, Plan, Change};
+use super::snapshot::Generation;
 
-use super::{Snapshot, Driver};
+/// The prefix of the user properties of Elephant Archive.
+pub const USER_PROPERTY_PREFIX: &str = "elephant:";
+
+/// The user property recording the checksum of the stream received on a snapshot.
+pub const CHECKSUM_PROPERTY: &str = "elephant:sha256";
+
+/// The user property recording the checksum of only the part of the stream received after a resume,
+/// which is not the checksum of the whole stream.
+pub const RESUMED_CHECKSUM_PROPERTY: &str = "elephant:sha256-resumed";
+
+/// The prefix of the tags holding the base snapshots of the next incremental streams.
+pub const HOLD_TAG_PREFIX: &str = "elephant:";
+
+/// The user property recording the base snapshots of the next incremental streams to every archive.
+/// The value is like `key=short_name,key=short_name`, the key is the hash of the hold tag of the archive.
+pub const BASES_PROPERTY: &str = "elephant:bases";
+
+/// The user property recording the expiry of a clone for inspection.
+pub const EXPIRES_PROPERTY: &str = "elephant:expires";
+
+/// The suffix of the filesystem receiving a restore beside the existing filesystem it replaces.
+const RESTORING_SUFFIX: &str = ".elephant-restoring";
+
+/// The suffix of the existing filesystem replaced by a restore until it is destroyed.
+const REPLACED_SUFFIX: &str = ".elephant-replaced";
 
 #[derive(Debug, Clone)]
 struct FilesystemAttribute {
@@ -17,12 +44,14 @@ struct FilesystemAttribute {
 }
 
 static ATTRIBUTE_INSTANCE: Lazy<Mutex<FilesystemAttribute>> = 
-        Lazy::new(|| Mutex::new(FilesystemAttribute::new()));
+        Lazy::new(|| Mutex::new(FilesystemAttribute::new(Driver::get_instance())));
 
-impl FilesystemAttribute {
-    fn new() -> FilesystemAttribute {
-        let driver = Driver::get_instance();
+/// The attributes of the archive running through a transport other than the local one.
+static ARCHIVE_ATTRIBUTE_INSTANCE: Lazy<Mutex<FilesystemAttribute>> = 
+        Lazy::new(|| Mutex::new(FilesystemAttribute::new(Driver::get_archive_instance())));
 
+impl FilesystemAttribute {
+    fn new(driver: &Driver) -> FilesystemAttribute {
         let filesystem_names = driver.get_filesystems();
         let snapshot_names = driver.get_snapshots();
 
@@ -33,49 +62,99 @@ impl FilesystemAttribute {
     }
 }
 
-struct FilesystemController;
+struct FilesystemController {
+    driver: &'static Driver,
+}
 
 impl FilesystemController {
     /// Get the FilesystemAttribute instance.
     /// The instance contains the attributes of the ZFS filesystem.
     fn global() -> FilesystemController {
-        FilesystemController{}
+        Self::of(Driver::get_instance())
+    }
+
+    /// Get the controller of the attributes on the machine `driver` runs on.
+    fn of(driver: &'static Driver) -> FilesystemController {
+        FilesystemController { driver }
+    }
+
+    fn attribute(&self) -> &'static Mutex<FilesystemAttribute> {
+        if self.driver.is_local() {
+            &ATTRIBUTE_INSTANCE
+        }
+        else {
+            &ARCHIVE_ATTRIBUTE_INSTANCE
+        }
+    }
+
+    /// Lock the attributes shared with the other threads.
+    /// The attributes are still used after a thread panicked while holding them,
+    /// they are consistent because they are replaced only after reloaded from the machine.
+    fn lock(&self) -> MutexGuard<'static, FilesystemAttribute> {
+        self.attribute().lock().unwrap_or_else(PoisonError::into_inner)
     }
 
     fn exist(&self, filesystem: &str) -> bool {
-        let fa = ATTRIBUTE_INSTANCE.lock().unwrap();
+        let fa = self.lock();
         let filesystems = fa.filesystems.clone();
 
         filesystems.contains(&filesystem.to_string())
     }
 
     fn get_snapshots(&self, filesystem: &str) -> Vec<String> {
-        let fa = ATTRIBUTE_INSTANCE.lock().unwrap();
+        let fa = self.lock();
         let snapshots = fa.snapshots.clone();
 
-        // filter `snapshots` with starting `name`.
+        // filter `snapshots` with starting `name@`.
+        let prefix = format!("{filesystem}@");
         let into_iter = snapshots.into_iter();
-        let filtered: Vec<String> = into_iter.filter(|x| x.starts_with(filesystem)).collect();
+        into_iter.filter(|x| x.starts_with(&prefix)).collect()
+    }
 
-        filtered
+    fn add_filesystem(&mut self, filesystem: &str) {
+        let mut attribute = self.lock();
+        attribute.filesystems.push(filesystem.to_string());
     }
 
     fn add_snapshot(&mut self, snapshot: &str) {
-        let mut attribute = ATTRIBUTE_INSTANCE.lock().unwrap();
+        let mut attribute = self.lock();
         attribute.snapshots.push(snapshot.to_string());
     }
 
-    fn destroy_snapshots(&mut self, destroys: &Vec<Snapshot>) {
-        let mut attribute = ATTRIBUTE_INSTANCE.lock().unwrap();
+    /// Reload the filesystems from the machine.
+    fn refresh_filesystems(&mut self) {
+        // reload under the lock not to lose the filesystems added by the other threads meanwhile.
+        let mut attribute = self.lock();
+        attribute.filesystems = self.driver.get_filesystems();
+    }
+
+    /// Reload the snapshots of the `filesystem` filesystem from the machine.
+    fn refresh_snapshots(&mut self, filesystem: &str) {
+        let prefix = format!("{filesystem}@");
+        let mut attribute = self.lock();
+        let mut snapshot_names = self.driver.get_snapshots_of(filesystem);
+        attribute.snapshots.retain(|s| !s.starts_with(&prefix));
+        attribute.snapshots.append(&mut snapshot_names);
+    }
+
+    /// Reload all of the filesystems and the snapshots from the machine.
+    fn refresh(&mut self) {
+        let mut attribute = self.lock();
+        *attribute = FilesystemAttribute::new(self.driver);
+    }
+
+    fn destroy_snapshots(&mut self, destroys: &[Snapshot]) {
+        let mut attribute = self.lock();
 
         attribute.snapshots
-                .retain(|s| destroys.iter().any(|d| d.name() != *s));
+                .retain(|s| !destroys.iter().any(|d| d.name() == *s));
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Filesystem {
     name: String,
+    driver: &'static Driver,
     controller: SnapshotCollector,
 }
 
@@ -87,6 +166,13 @@ impl Filesystem {
         attribute.exist(name)
     }
 
+    /// Confirm the `name` ZFS filesystem exists on the archive or not.
+    pub fn exist_archive(name: &str) -> bool {
+        let attribute = FilesystemController::of(Driver::get_archive_instance());
+
+        attribute.exist(name)
+    }
+
     /// Make a ZFS filesystem instance from `name`.
     pub fn from(name: &str) -> Result<Filesystem, String> {
         if !Self::exist(name) {
@@ -95,68 +181,953 @@ impl Filesystem {
             return Err(message);
         }
 
-        Ok(Self::new(name))
+        Ok(Self::new(name, Driver::get_instance()))
+    }
+
+    /// Find the mounted ZFS filesystem containing `path` on this machine.
+    /// Return the filesystem and the path relative to its mountpoint.
+    pub fn containing(path: &Path) -> Result<(Filesystem, PathBuf), String> {
+        let mountpoints = Driver::get_instance().get_mountpoints();
+
+        // the deepest mountpoint contains `path`.
+        let found = mountpoints.iter()
+                .filter(|(_, mountpoint)| mountpoint.starts_with('/'))
+                .filter_map(|(name, mountpoint)| {
+                    let relative = path.strip_prefix(mountpoint).ok()?;
+                    Some((name, mountpoint.len(), relative.to_path_buf()))
+                })
+                .max_by_key(|(_, length, _)| *length);
+
+        match found {
+            Some((name, _, relative)) => Ok((Self::from(name)?, relative)),
+            None => Err(format!("'{}' is not in a mounted ZFS filesystem", path.display())),
+        }
+    }
+
+    /// Get the path of `relative` in `snapshot` of this filesystem,
+    /// which is under the hidden `.zfs/snapshot` directory of the mountpoint.
+    pub fn snapshot_path(&self, snapshot: &Snapshot, relative: &Path) -> PathBuf {
+        let mountpoint = self.driver.get_property(&self.name, "mountpoint");
+
+        Path::new(&mountpoint).join(".zfs/snapshot").join(snapshot.short_name()).join(relative)
+    }
+
+    /// Clone `snapshot` of this filesystem to a filesystem under `parent` for inspection.
+    /// The clone is mounted read-only unless `writable` is true, and it expires at `expires`.
+    /// Return the clone.
+    pub fn clone_snapshot(&self, snapshot: &Snapshot, parent: &str, writable: bool,
+            expires: DateTime<Local>) -> Result<Filesystem, String> {
+        let name = format!("{parent}/{}.{}", self.name.replace('/', "_"), snapshot.short_name());
+        let clone = Self::new(&name, self.driver);
+        if clone.is_present() {
+            return Err(format!("The '{name}' clone exists, clean it up first"));
+        }
+
+        if !Self::exist(parent) {
+            self.driver.create_filesystem(parent)?;
+        }
+
+        let readonly = if writable { "off" } else { "on" };
+        let properties = [
+            ("readonly".to_string(), readonly.to_string()),
+            (EXPIRES_PROPERTY.to_string(), expires.to_rfc3339()),
+        ];
+        elephant_log::info!("clone: {} to {name}", snapshot.name());
+        self.driver.clone_snapshot(&snapshot.name(), &name, &properties)?;
+
+        let mut attribute = FilesystemController::of(self.driver);
+        attribute.refresh_filesystems();
+
+        Ok(clone)
+    }
+
+    /// Get the clones for inspection on this machine as pairs of a clone and its expiry.
+    pub fn inspection_clones() -> Vec<(String, DateTime<Local>)> {
+        let values = Driver::get_instance().get_property_all(EXPIRES_PROPERTY);
+
+        // only the clones with the expiry are cloned for inspection.
+        values.into_iter()
+                .filter_map(|(name, value)| {
+                    let expires = DateTime::parse_from_rfc3339(&value).ok()?;
+                    Some((name, DateTime::from(expires)))
+                })
+                .collect()
+    }
+
+    /// Destroy this filesystem with its snapshots.
+    pub fn destroy(self) -> Result<(), String> {
+        elephant_log::info!("destroy: {}", self.name);
+        self.driver.destroy_filesystem(&self.name)?;
+
+        let mut attribute = FilesystemController::of(self.driver);
+        attribute.refresh_filesystems();
+
+        Ok(())
+    }
+
+    /// Make a ZFS filesystem instance on the archive from `name`.
+    pub fn from_archive(name: &str) -> Result<Filesystem, String> {
+        if !Self::exist_archive(name) {
+            let message = format!("'{}' filesystem is not exist on the archive.", name);
+            elephant_log::error!("{message}");
+            return Err(message);
+        }
+
+        Ok(Self::new(name, Driver::get_archive_instance()))
     }
 
     /// Archive a ZFS filesystem to a ZFS filesystem containing replications.
+    /// The whole of the newest snapshot is sent at the first time,
+    /// and only the differences from the newest common snapshot are sent after that.
+    /// Return the snapshot instance archived on `replication`.
     /// # Arguments
-    /// - `replication` - An array of snapshot instance that will be destroyed.
-    /// - `original` - An interval time to keep `snapshots`.
-    pub fn archive(replication: &mut Filesystem, original: &Filesystem) {
+    /// - `replication` - A ZFS filesystem containing the replications.
+    /// - `original` - A ZFS filesystem archived into `replication`.
+    /// - `option` - The options to send the streams.
+    pub fn archive(replication: &Filesystem, original: &Filesystem,
+            option: &SendOption) -> Result<Snapshot, String> {
+        let snapshot = match original.snapshots().last() {
+            Some(snapshot) => snapshot.clone(),
+            None => {
+                let message = format!("'{}' filesystem has no snapshot to archive.", original.name());
+                elephant_log::error!("{message}");
+                return Err(message);
+            },
+        };
+
+        let mut replica = replication.replica(original);
+        original.check_plaintext(&replica)?;
+        // roll back the replica only when it has diverged from the base.
+        let rollback = option.rollback;
+        let mut option = SendOption { rollback: false, ..original.send_option(option) };
+        let sender = original.driver;
+        let receiver = replica.driver;
+
+        if let Some(token) = replica.resume_token() {
+            elephant_log::display!("Resume the interrupted archive on '{}'", replica.name);
+            let transfer = sender.resume_send_receive(&token, &replica.name, &option, receiver)?;
+
+            replica.refresh();
+            // the resumed stream is checksummed from the point it was resumed.
+            if let Some(resumed) = replica.snapshots().last() {
+                Self::record_checksum(&replica, resumed, &transfer, RESUMED_CHECKSUM_PROPERTY)?;
+            }
+        }
+
+        // the parent created to receive the child filesystems is present, but it has nothing in common.
+        let placeholder = replica.is_placeholder();
+        let (sents, transfers) = if replica.is_present() && !placeholder {
+            let base = original.incremental_base(&replica, &option)?;
+
+            if base.short_name() == snapshot.short_name() {
+                elephant_log::info!("'{}' filesystem already has {}", replica.name, snapshot.short_name());
+                Self::hold_base(original, &replica, &snapshot, option.recursive)?;
+                return Ok(replica.snapshot_of(&snapshot));
+            }
+
+            // the stream cannot be received on the replication changed after the base.
+            let divergences = replica.divergences(&base);
+            if !divergences.is_empty() {
+                let diagnosis = divergences.join(", ");
+                if !rollback {
+                    let message = format!("'{}' filesystem has diverged from '{}': {diagnosis}, \
+                            archive it with the '--rollback' option to roll back to {} destroying them.",
+                            replica.name, original.name(), base.short_name());
+                    elephant_log::error!("{message}");
+                    return Err(message);
+                }
+                elephant_log::display!("Roll back '{}' to {}: {diagnosis}", replica.name, base.short_name());
+                option.rollback = true;
+            }
 
+            elephant_log::info!("archive: {} from {} to {}", snapshot.name(), base.name(), replica.name);
+            let sents = original.newer_than(&base);
+            let transfers = if base.is_bookmark() {
+                // the snapshots after the first one are sent from it with the intermediary snapshots.
+                let first = match sents.first() {
+                    Some(first) => first,
+                    None => {
+                        let message = format!("'{}' filesystem has no snapshot after {}.", original.name(), base.name());
+                        elephant_log::error!("{message}");
+                        return Err(message);
+                    },
+                };
+                let transfer = sender.send_receive_from(&base.name(), &first.name(),
+                        &replica.name, &option, receiver)?;
+                let mut transfers = vec![(first.clone(), transfer)];
+                if *first != snapshot {
+                    let transfer = sender.send_receive_incremental(&first.name(), &snapshot.name(),
+                            &replica.name, &option, receiver)?;
+                    transfers.push((snapshot.clone(), transfer));
+                }
+                transfers
+            }
+            else {
+                let transfer = sender.send_receive_incremental(&base.name(), &snapshot.name(),
+                        &replica.name, &option, receiver)?;
+                vec![(snapshot.clone(), transfer)]
+            };
+
+            (sents, transfers)
+        }
+        else {
+            elephant_log::info!("archive: {} to {}", snapshot.name(), replica.name);
+            if placeholder {
+                // a replication stream would destroy the child filesystems not under the original.
+                if option.recursive {
+                    let message = format!("'{}' filesystem was created for its child filesystems on the archive, \
+                            archive '{}' without the '--recursive' option, or destroy it first.",
+                            replica.name, original.name());
+                    elephant_log::error!("{message}");
+                    return Err(message);
+                }
+                elephant_log::info!("'{}' filesystem was created for its child filesystems, overwrite it", replica.name);
+                option.rollback = true;
+            }
+            else {
+                replica.create_parent()?;
+            }
+
+            let transfer = match sender.send_receive(&snapshot.name(), &replica.name, &option, receiver) {
+                // the parent may be created meanwhile for the child filesystems archived in parallel.
+                Err(_) if !option.recursive && !option.rollback && replica.is_placeholder() => {
+                    elephant_log::info!("'{}' filesystem was created for its child filesystems, overwrite it", replica.name);
+                    option.rollback = true;
+                    sender.send_receive(&snapshot.name(), &replica.name, &option, receiver)?
+                },
+                transfer => transfer?,
+            };
+
+            let mut attribute = FilesystemController::of(receiver);
+            if !attribute.exist(&replica.name) {
+                attribute.add_filesystem(&replica.name);
+            }
+
+            (vec![snapshot.clone()], vec![(snapshot.clone(), transfer)])
+        };
+
+        let mut attribute = FilesystemController::of(receiver);
+        if option.recursive {
+            // the descendant filesystems are received with their snapshots.
+            attribute.refresh();
+        }
+        else if option.rollback {
+            // the snapshots after the base have been destroyed by the rollback.
+            attribute.refresh_snapshots(&replica.name);
+        }
+        else {
+            for sent in sents.iter() {
+                attribute.add_snapshot(&replica.snapshot_of(sent).name());
+            }
+        }
+
+        // record the checksum of every stream alongside the newest snapshot it received.
+        for (received, transfer) in transfers.iter() {
+            Self::record_checksum(&replica, received, transfer, CHECKSUM_PROPERTY)?;
+        }
+
+        Self::hold_base(original, &replica, &snapshot, option.recursive)?;
+
+        Ok(replica.snapshot_of(&snapshot))
     }
 
-    /// Create a ZFS filesystem instance from `name`.
-    fn new(name: &str) -> Filesystem {
-        let controller = SnapshotCollector::new(name);
+    /// Record the checksum of `transfer` on the `snapshot` snapshot received on `replica` as `property`.
+    fn record_checksum(replica: &Filesystem, snapshot: &Snapshot, transfer: &Transfer,
+            property: &str) -> Result<(), String> {
+        let received = replica.snapshot_of(snapshot);
+        elephant_log::info!("received {} bytes with sha256 {} to {}", transfer.bytes, transfer.sha256, received.name());
+        replica.driver.set_property(&received.name(), property, &transfer.sha256)
+    }
 
-        let instance = Filesystem {
-            name: name.to_string(),
-            controller,
+    /// Make the plan archiving `original` to `replication` without archiving it.
+    /// The plan shows the snapshots sent with their estimated bytes and the space available.
+    pub fn plan(replication: &Filesystem, original: &Filesystem, option: &SendOption) -> Result<Plan, String> {
+        let snapshot = match original.snapshots().last() {
+            Some(snapshot) => snapshot.clone(),
+            None => return Err(format!("'{}' filesystem has no snapshot to archive.", original.name())),
+        };
+
+        let replica = replication.replica(original);
+        let option = original.send_option(option);
+        let sender = original.driver;
+
+        let mut plan = Plan {
+            source: original.name(),
+            target: replica.name(),
+            ..Plan::default()
+        };
+        if let Err(message) = original.check_plaintext(&replica) {
+            plan.notes.push(message);
+        }
+        if replica.resume_token().is_some() {
+            plan.notes.push("the interrupted archive is resumed or aborted first".to_string());
+        }
+
+        if !replica.is_present() {
+            plan.kind = "full".to_string();
+            plan.streams = sender.estimate_streams(None, &snapshot.name(), false, &option)?;
+            plan.available = replication.available();
+            return Ok(plan);
+        }
+
+        plan.available = replica.available();
+        let base = original.incremental_base(&replica, &option)?;
+        if base.short_name() == snapshot.short_name() {
+            plan.kind = format!("up to date with {}", snapshot.short_name());
+            return Ok(plan);
+        }
+
+        plan.kind = format!("incremental from {}", base.name());
+        plan.notes.append(&mut replica.divergences(&base));
+        if base.is_bookmark() {
+            let newers = original.newer_than(&base);
+            let first = match newers.first() {
+                Some(first) => first,
+                None => return Err(format!("'{}' filesystem has no snapshot after {}.", original.name(), base.name())),
+            };
+            plan.streams = sender.estimate_streams(Some(&base.name()), &first.name(), false, &option)?;
+            if *first != snapshot {
+                let mut streams = sender.estimate_streams(Some(&first.name()), &snapshot.name(), true, &option)?;
+                plan.streams.append(&mut streams);
+            }
+        }
+        else {
+            plan.streams = sender.estimate_streams(Some(&base.name()), &snapshot.name(), true, &option)?;
+        }
+
+        Ok(plan)
+    }
+
+    /// Restore `snapshot` of this replication to the `target` filesystem on this machine.
+    /// The existing `target` filesystem is overwritten only if `force` is true.
+    /// Return the snapshot instance restored.
+    pub fn restore(&self, snapshot: &Snapshot, target: &str, force: bool,
+            option: &SendOption) -> Result<Snapshot, String> {
+        let receiving = Self::receiving(target, force)?;
+        let option = self.send_option(option);
+
+        elephant_log::info!("restore: {} to {}", snapshot.name(), receiving.name);
+        self.driver.send_receive(&snapshot.name(), &receiving.name, &option, receiving.driver)?;
+        receiving.refresh_restored();
+        let restored = receiving.replace(target)?;
+
+        Ok(restored.snapshot_of(snapshot))
+    }
+
+    /// Prepare the filesystem receiving the restore of the `target` filesystem on this machine.
+    /// The existing `target` filesystem is overwritten only if `force` is true,
+    /// then the restore is received beside it and replaces it by `replace` after received.
+    pub fn receiving(target: &str, force: bool) -> Result<Filesystem, String> {
+        let restored = Self::new(target, Driver::get_instance());
+        if !restored.is_present() {
+            restored.create_parent()?;
+            return Ok(restored);
+        }
+
+        if !force {
+            let message = format!("The '{target}' ZFS filesystem exists, restore it with the '--force' option to overwrite it");
+            elephant_log::error!("{message}");
+            return Err(message);
+        }
+        if !target.contains('/') {
+            let message = format!("The '{target}' ZFS filesystem is the root of the pool and cannot be overwritten, \
+                    restore it to another filesystem");
+            elephant_log::error!("{message}");
+            return Err(message);
+        }
+
+        // the child filesystems are not in the restored stream, they would be destroyed with the replaced filesystem.
+        let children = restored.children();
+        if !children.is_empty() {
+            let message = format!("The '{target}' ZFS filesystem has the child filesystems {}, \
+                    restore it to another filesystem not to destroy them", children.join(" "));
+            elephant_log::error!("{message}");
+            return Err(message);
+        }
+
+        // the filesystem left by an interrupted restore is received again.
+        let receiving = Self::new(&format!("{target}{RESTORING_SUFFIX}"), restored.driver);
+        if receiving.is_present() {
+            elephant_log::warn!("destroy the interrupted restore: {}", receiving.name);
+            receiving.driver.destroy_filesystem(&receiving.name)?;
+            FilesystemController::of(receiving.driver).refresh_filesystems();
+        }
+
+        Ok(receiving)
+    }
+
+    /// Replace the `target` filesystem with this filesystem prepared by `receiving` and received.
+    /// The existing `target` filesystem is destroyed only after this filesystem has taken its name,
+    /// it is left with a warning if it cannot be destroyed.
+    /// Return the filesystem restored.
+    pub fn replace(self, target: &str) -> Result<Filesystem, String> {
+        if self.name == target {
+            return Ok(self);
+        }
+
+        let replaced = format!("{target}{REPLACED_SUFFIX}");
+        elephant_log::info!("replace: {target} with {}", self.name);
+        self.driver.rename(target, &replaced)?;
+        if let Err(message) = self.driver.rename(&self.name, target) {
+            // the existing filesystem is put back, the restore is kept on this filesystem.
+            elephant_log::error!("Failed to replace '{target}', the restore is kept on '{}': {message}", self.name);
+            self.driver.rename(&replaced, target)?;
+            return Err(message);
+        }
+        if let Err(message) = self.destroy_replaced(&replaced) {
+            elephant_log::warn!("The replaced filesystem is left on '{replaced}', destroy it manually: {message}");
+        }
+
+        let mut attribute = FilesystemController::of(self.driver);
+        attribute.refresh();
+
+        Ok(Self::new(target, self.driver))
+    }
+
+    /// Destroy the `replaced` filesystem after releasing the holds of Elephant Archive on its snapshots.
+    /// The holds kept the bases of the archives, the archives are restarted from the restored filesystem.
+    fn destroy_replaced(&self, replaced: &str) -> Result<(), String> {
+        let snapshots = self.driver.get_snapshots_of(replaced);
+        let holds = self.driver.holds(&snapshots);
+        for (held, tag) in holds.iter().filter(|(_, tag)| tag.starts_with(HOLD_TAG_PREFIX)) {
+            elephant_log::info!("release {tag} from {held}");
+            self.driver.release(held, tag, false)?;
+        }
+
+        elephant_log::info!("destroy: {replaced}");
+        self.driver.destroy_filesystem(replaced)
+    }
+
+    /// Get the names of the descendant filesystems of this filesystem.
+    fn children(&self) -> Vec<String> {
+        let prefix = format!("{}/", self.name);
+        self.driver.get_filesystems().into_iter()
+                .filter(|f| f.starts_with(&prefix))
+                .collect()
+    }
+
+    /// Receive the stream in the `path` file on this filesystem restored.
+    /// The stream passes through the `filters` command lines before received, like a decompressor.
+    pub fn receive_file(&self, path: &Path, filters: &[String], option: &SendOption) -> Result<Transfer, String> {
+        elephant_log::info!("restore: {} to {}", path.display(), self.name);
+        let received = self.driver.receive_file(path, filters, &self.name, option);
+        self.refresh_restored();
+
+        received
+    }
+
+    /// Reload the filesystems and the snapshots of this filesystem restored.
+    fn refresh_restored(&self) {
+        let mut attribute = FilesystemController::of(self.driver);
+        attribute.refresh_filesystems();
+        attribute.refresh_snapshots(&self.name);
+    }
+
+    /// Find the base of the incremental stream from this filesystem to `replica`.
+    /// A replication stream of the descendant filesystems cannot start from a bookmark.
+    fn incremental_base(&self, replica: &Filesystem, option: &SendOption) -> Result<Snapshot, String> {
+        let base = if option.recursive {
+            self.newest_common(&replica.snapshots())
+        }
+        else {
+            self.newest_base(&replica.snapshots())
         };
 
-        instance
+        match base {
+            Some(base) => Ok(base),
+            None => {
+                let message = format!("'{}' filesystem has no snapshot in common with '{}'.",
+                        replica.name, self.name);
+                elephant_log::error!("{message}");
+                Err(message)
+            },
+        }
+    }
+
+    /// Get the bytes available on this filesystem, or `None` if unknown.
+    pub fn available(&self) -> Option<u64> {
+        self.driver.get_property(&self.name, "available").parse().ok()
+    }
+
+    /// Hold `snapshot` archived on `replica`, and release the snapshot held on the previous archive.
+    /// The held snapshot is the base of the next incremental stream, kept on `original` by `keep_base`.
+    fn hold_base(original: &Filesystem, replica: &Filesystem, snapshot: &Snapshot,
+            recursive: bool) -> Result<(), String> {
+        let tag = format!("{HOLD_TAG_PREFIX}{}", replica.name);
+        replica.move_hold(&replica.snapshot_of(snapshot), &tag, recursive)?;
+        original.keep_base(snapshot, &tag, recursive)
+    }
+
+    /// Keep `snapshot` of this filesystem as the base of the next incremental stream to the archive held with `tag`.
+    /// The bookmark of `snapshot` is the base and the snapshot is released from `tag` to be purged,
+    /// but a replication stream of the descendant filesystems if `recursive` needs the snapshots held on all of them.
+    /// The bookmarks no longer the base of any archive are destroyed.
+    pub fn keep_base(&self, snapshot: &Snapshot, tag: &str, recursive: bool) -> Result<(), String> {
+        self.create_bookmark(snapshot)?;
+        if recursive {
+            self.move_hold(snapshot, tag, true)?;
+        }
+        else {
+            self.release_hold(tag)?;
+        }
+
+        self.record_base(snapshot, tag)?;
+        self.prune_bookmarks();
+
+        Ok(())
+    }
+
+    /// Release the hold with `tag` from every snapshot of this filesystem.
+    fn release_hold(&self, tag: &str) -> Result<(), String> {
+        let names: Vec<String> = self.snapshots().iter().map(|s| s.name()).collect();
+        let holds = self.driver.holds(&names);
+        for (held, _) in holds.iter().filter(|(_, t)| t == tag) {
+            self.release(held, tag)?;
+        }
+
+        Ok(())
+    }
+
+    /// Record `snapshot` as the base of the next incremental stream to the archive held with `tag`.
+    fn record_base(&self, snapshot: &Snapshot, tag: &str) -> Result<(), String> {
+        let digest = Sha256::digest(tag.as_bytes());
+        let key: String = digest.iter().take(8).map(|b| format!("{b:02x}")).collect();
+
+        let mut bases: Vec<(String, String)> = self.bases().into_iter()
+                .filter(|(k, _)| *k != key)
+                .collect();
+        bases.push((key, snapshot.short_name()));
+
+        let value: Vec<String> = bases.iter().map(|(k, s)| format!("{k}={s}")).collect();
+        self.driver.set_property(&self.name, BASES_PROPERTY, &value.join(","))
+    }
+
+    /// Get the base snapshots recorded on this filesystem as pairs of a key and a short name.
+    fn bases(&self) -> Vec<(String, String)> {
+        // the bases are not inherited, the parent filesystem records the bases of its own archives.
+        let value = self.driver.get_local_property(&self.name, BASES_PROPERTY);
+
+        value.split(',')
+                .filter_map(|b| b.split_once('='))
+                .map(|(k, s)| (k.to_string(), s.to_string()))
+                .collect()
+    }
+
+    /// Hold `snapshot` of this filesystem with `tag`,
+    /// and then release `tag` from the other snapshots of this filesystem.
+    /// The snapshots of the descendant filesystems are held and released together if `recursive` is true.
+    pub fn move_hold(&self, snapshot: &Snapshot, tag: &str, recursive: bool) -> Result<(), String> {
+        let mut names: Vec<String> = self.snapshots().iter().map(|s| s.name()).collect();
+        if !names.contains(&snapshot.name()) {
+            names.push(snapshot.name());
+        }
+        let holds = self.driver.holds(&names);
+
+        let held = holds.iter().any(|(s, t)| *s == snapshot.name() && t == tag);
+        if !held {
+            elephant_log::info!("hold {} with {tag}", snapshot.name());
+            self.driver.hold(&snapshot.name(), tag, recursive)?;
+        }
+
+        let previous = holds.iter().filter(|(s, t)| *s != snapshot.name() && t == tag);
+        for (previous, _) in previous {
+            elephant_log::info!("release {tag} from {previous}");
+            self.driver.release(previous, tag, recursive)?;
+        }
+
+        Ok(())
+    }
+
+    /// Confirm the snapshots archived from `original` on `replication` are the same
+    /// as the snapshots on `original` by comparing their GUIDs.
+    /// Return the problems found on the replication.
+    pub fn verify(replication: &Filesystem, original: &Filesystem) -> Vec<String> {
+        let replica = replication.replica(original);
+        if !replica.is_present() {
+            return vec![format!("'{}' filesystem is not found on the archive", replica.name)];
+        }
+
+        let mut problems = Vec::new();
+        let replica_snapshots = replica.snapshots();
+        for snapshot in original.snapshots() {
+            let received = replica.snapshot_of(&snapshot);
+            if !replica_snapshots.contains(&received) {
+                continue;
+            }
+
+            let guid = original.guid(&snapshot);
+            let received_guid = replica.guid(&received);
+            if guid == received_guid {
+                elephant_log::info!("verified: {}", received.name());
+            }
+            else {
+                problems.push(format!("'{}' has the GUID {received_guid}, expected {guid}", received.name()));
+            }
+        }
+
+        problems
+    }
+
+    /// Create a ZFS filesystem instance from `name` on the machine `driver` runs on.
+    fn new(name: &str, driver: &'static Driver) -> Filesystem {
+        let controller = SnapshotCollector::new(name, driver);
+
+        Filesystem {
+            name: name.to_string(),
+            driver,
+            controller,
+        }
     }
 
     pub fn name(&self) -> String {
         self.name.clone()
     }
 
+    /// Confirm this filesystem exists or not.
+    pub fn is_present(&self) -> bool {
+        FilesystemController::of(self.driver).exist(&self.name)
+    }
+
+    /// Reload the snapshots of this filesystem.
+    fn refresh(&mut self) {
+        let mut attribute = FilesystemController::of(self.driver);
+        attribute.refresh_filesystems();
+        attribute.refresh_snapshots(&self.name);
+
+        self.controller = SnapshotCollector::new(&self.name, self.driver);
+    }
+
     // Get the snapshot instances
     pub fn snapshots(&self) -> Vec<Snapshot> {
         self.controller.snapshots()
     }
 
+    /// Get the snapshot instance on this filesystem having the same short name as `snapshot`.
+    pub fn snapshot_of(&self, snapshot: &Snapshot) -> Snapshot {
+        Snapshot::new(&format!("{}@{}", self.name, snapshot.short_name()))
+    }
+
     /// Take a snapshot
     pub fn take_snapshot(&mut self) -> Snapshot {
-        let snapshot = self.controller.take();
-        snapshot
+        self.controller.take(true)
     }
 
-    // Purge the some snapshots
-    pub fn purge_snapshots(&mut self) -> Vec<Snapshot> {
-        let destroys = self.controller.purge();
+    /// Take a snapshot of this filesystem only, not of the descendant filesystems.
+    pub fn take_own_snapshot(&mut self) -> Snapshot {
+        self.controller.take(false)
+    }
+
+    /// Purge the snapshots of this filesystem over `retention` except `protects`.
+    pub fn purge_snapshots(&mut self, retention: &Retention, protects: &[Snapshot]) -> Vec<Snapshot> {
+        let destroys = self.controller.purge(retention, protects);
+        self.refresh();
         destroys
     }
 
+    /// Confirm this filesystem is encrypted or not.
+    pub fn is_encrypted(&self) -> bool {
+        let encryption = self.driver.get_property(&self.name, "encryption");
+
+        encryption != "off"
+    }
+
+    /// Decide the options to send this filesystem from the options requested with `option`.
+    /// An encrypted filesystem is sent as a raw stream to keep it encrypted on the archive.
+    pub fn send_option(&self, option: &SendOption) -> SendOption {
+        if !self.is_encrypted() {
+            return SendOption { raw: false, ..option.clone() };
+        }
+
+        let keystatus = self.driver.get_property(&self.name, "keystatus");
+        elephant_log::info!("'{}' filesystem is encrypted with the key {keystatus}", self.name);
+
+        SendOption { raw: true, ..option.clone() }
+    }
+
+    /// Confirm the `replica` filesystem does not store this encrypted filesystem as plaintext.
+    /// A raw stream cannot be received on the plaintext replication.
+    fn check_plaintext(&self, replica: &Filesystem) -> Result<(), String> {
+        if !replica.is_present() || replica.is_encrypted() || !self.is_encrypted() {
+            return Ok(());
+        }
+
+        let message = format!("'{}' filesystem stores the encrypted '{}' filesystem as plaintext, \
+                archive it to another filesystem.", replica.name, self.name);
+        elephant_log::error!("{message}");
+        Err(message)
+    }
+
+    /// Get the value of the `property` property on this filesystem.
+    /// Return `None` if the property is not set.
+    pub fn property(&self, property: &str) -> Option<String> {
+        let value = self.driver.get_property(&self.name, property);
+        if value == "-" || value.is_empty() {
+            return None;
+        }
+
+        Some(value)
+    }
+
+    /// Get the GUID of the `snapshot` snapshot on this filesystem.
+    pub fn guid(&self, snapshot: &Snapshot) -> String {
+        self.driver.get_property(&snapshot.name(), "guid")
+    }
+
+    /// Find the newest snapshot having the same short name as one of `others`.
+    pub fn newest_common(&self, others: &[Snapshot]) -> Option<Snapshot> {
+        self.controller.newest_common(others)
+    }
+
+    /// Get the snapshots taken after `base`, a snapshot or a bookmark.
+    pub fn newer_than(&self, base: &Snapshot) -> Vec<Snapshot> {
+        self.controller.newer_than(base)
+    }
+
+    /// Get the bookmarks of this filesystem created by Elephant Archive.
+    pub fn bookmarks(&self) -> Vec<Snapshot> {
+        if !self.is_present() {
+            return Vec::new();
+        }
+
+        self.driver.get_bookmarks_of(&self.name).iter()
+                .filter(|b| Snapshot::is_elephant(b))
+                .map(|b| Snapshot::new(b))
+                .collect()
+    }
+
+    /// Create the bookmark of `snapshot` on this filesystem unless it exists.
+    /// The bookmark is the base of the next incremental stream after `snapshot` is purged.
+    pub fn create_bookmark(&self, snapshot: &Snapshot) -> Result<(), String> {
+        let bookmark = snapshot.bookmark();
+        if self.bookmarks().contains(&bookmark) {
+            return Ok(());
+        }
+
+        elephant_log::info!("bookmark {} as {}", snapshot.name(), bookmark.name());
+        self.driver.create_bookmark(&snapshot.name(), &bookmark.name())
+    }
+
+    /// Destroy the bookmarks which are not the base of the incremental stream to any archive.
+    /// The bases are recorded for every archive, or held by Elephant Archive if the archive is recursive.
+    fn prune_bookmarks(&self) {
+        let mut bases: Vec<String> = self.bases().into_iter().map(|(_, s)| s).collect();
+
+        let names: Vec<String> = self.snapshots().iter().map(|s| s.name()).collect();
+        let helds = self.driver.holds(&names).into_iter()
+                .filter(|(_, tag)| tag.starts_with(HOLD_TAG_PREFIX))
+                .map(|(held, _)| Snapshot::new(&held).short_name());
+        bases.extend(helds);
+
+        for bookmark in self.bookmarks().iter().filter(|b| !bases.contains(&b.short_name())) {
+            elephant_log::info!("destroy the bookmark: {}", bookmark.name());
+            if let Err(message) = self.driver.destroy_bookmark(&bookmark.name()) {
+                elephant_log::warn!("Failed to destroy the bookmark {}: {message}", bookmark.name());
+            }
+        }
+    }
+
+    /// Find the newest snapshot or bookmark having the same short name as one of `others`.
+    /// A snapshot is preferred to a bookmark of the same short name.
+    pub fn newest_base(&self, others: &[Snapshot]) -> Option<Snapshot> {
+        let snapshot = self.newest_common(others);
+
+        let short_names: Vec<String> = others.iter().map(|s| s.short_name()).collect();
+        let bookmark = self.bookmarks().into_iter().rev()
+                .find(|b| short_names.contains(&b.short_name()));
+
+        match (snapshot, bookmark) {
+            (Some(snapshot), Some(bookmark)) if bookmark.datetime() > snapshot.datetime() => Some(bookmark),
+            (Some(snapshot), _) => Some(snapshot),
+            (None, bookmark) => bookmark,
+        }
+    }
+
+    /// Send `snapshot` of this filesystem to the `path` file, only the differences from `base` if any.
+    /// The stream passes through the `filters` command lines before written to `path`.
+    /// Return the number of bytes of the stream sent and the checksum of the `path` file.
+    pub fn send_file(&self, snapshot: &Snapshot, base: Option<&Snapshot>, option: &SendOption,
+            filters: &[String], path: &Path) -> Result<Transfer, String> {
+        let option = self.send_option(option);
+        let base = base.map(|b| b.name());
+        self.driver.send_file(&snapshot.name(), base.as_deref(), &option, filters, path)
+    }
+
+    /// Find the changes on this replication after `base`, the snapshot received last time.
+    /// The changes are the snapshots taken after `base` and the data written after the newest snapshot.
+    /// Return the descriptions of the changes, or an empty array if this replication has not diverged.
+    pub fn divergences(&self, base: &Snapshot) -> Vec<String> {
+        let mut divergences = Vec::new();
+
+        // every snapshot counts, even if it was not taken by Elephant Archive.
+        let names = self.driver.get_snapshots_of(&self.name);
+        let position = names.iter().position(|n| Snapshot::new(n).short_name() == base.short_name());
+        if let Some(index) = position {
+            let newers: Vec<String> = names[index + 1..].iter().map(|n| Snapshot::new(n).short_name()).collect();
+            if !newers.is_empty() {
+                divergences.push(format!("{} snapshots after {} ({})",
+                        newers.len(), base.short_name(), newers.join(" ")));
+            }
+        }
+
+        let written = self.driver.get_property(&self.name, "written");
+        if written.parse::<u64>().is_ok_and(|w| w > 0) {
+            divergences.push(format!("{written} bytes written after the newest snapshot"));
+        }
+
+        divergences
+    }
+
+    /// Get the snapshots taken after `snapshot` on this filesystem, even if they are not taken by Elephant Archive.
+    pub fn snapshots_after(&self, snapshot: &Snapshot) -> Vec<String> {
+        let names = self.driver.get_snapshots_of(&self.name);
+        match names.iter().position(|n| *n == snapshot.name()) {
+            Some(index) => names[index + 1..].to_vec(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Get the clones of the `snapshots` snapshots of this filesystem.
+    pub fn clones(&self, snapshots: &[String]) -> Vec<String> {
+        snapshots.iter().flat_map(|s| self.driver.get_clones(s)).collect()
+    }
+
+    /// Get the holds on the `snapshots` snapshots of this filesystem as pairs of a snapshot and a tag.
+    pub fn holds(&self, snapshots: &[String]) -> Vec<(String, String)> {
+        self.driver.holds(snapshots)
+    }
+
+    /// Release the hold with `tag` from the `snapshot` snapshot of this filesystem.
+    pub fn release(&self, snapshot: &str, tag: &str) -> Result<(), String> {
+        elephant_log::info!("release {tag} from {snapshot}");
+        self.driver.release(snapshot, tag, false)
+    }
+
+    /// Roll this filesystem back to `snapshot`, destroying the snapshots and the bookmarks after it.
+    /// The clones of the destroyed snapshots are destroyed only if `destroy_clones` is true.
+    pub fn rollback(&mut self, snapshot: &Snapshot, destroy_clones: bool) -> Result<(), String> {
+        elephant_log::info!("rollback: {}", snapshot.name());
+        let rolled = self.driver.rollback(&snapshot.name(), destroy_clones);
+
+        // the snapshots may be destroyed even if the rollback fails.
+        self.refresh();
+        rolled
+    }
+
+    /// Get the changes on the files of this filesystem from `snapshot` to `other`,
+    /// a later snapshot or the live filesystem if it is `None`.
+    pub fn diff(&self, snapshot: &Snapshot, other: Option<&Snapshot>) -> Result<Vec<Change>, String> {
+        let other = other.map_or_else(|| self.name.clone(), |o| o.name());
+        let lines = self.driver.diff(&snapshot.name(), &other)?;
+
+        lines.iter().map(|line| Change::parse(line)).collect()
+    }
+
+    /// Estimate the bytes of the stream of `snapshot` of this filesystem,
+    /// only the differences from `base` if any.
+    /// Return pairs of a snapshot and its estimated bytes.
+    pub fn estimate(&self, snapshot: &Snapshot, base: Option<&Snapshot>, option: &SendOption)
+            -> Result<Vec<(String, u64)>, String> {
+        let option = self.send_option(option);
+        let base = base.map(|b| b.name());
+        self.driver.estimate_streams(base.as_deref(), &snapshot.name(), false, &option)
+    }
+
+    /// Get the token to resume the receive interrupted on this filesystem.
+    /// Return `None` if this filesystem does not exist or no receive is interrupted on it.
+    pub fn resume_token(&self) -> Option<String> {
+        if !self.is_present() {
+            return None;
+        }
+
+        let token = self.driver.get_property(&self.name, "receive_resume_token");
+        if token == "-" {
+            return None;
+        }
+
+        Some(token)
+    }
+
+    /// Confirm the receive interrupted on this filesystem can be resumed from `original`.
+    /// The receive cannot be resumed if the snapshot sending has been destroyed.
+    pub fn check_resume(&self, original: &Filesystem, token: &str) -> Result<(), String> {
+        original.driver.check_resume(token)
+                .map_err(|_| format!("The interrupted archive on '{}' cannot be resumed", self.name))
+    }
+
+    /// Abort the receive interrupted on this filesystem.
+    /// This filesystem disappears if the interrupted receive was the first one.
+    pub fn abort_receive(&mut self) -> Result<(), String> {
+        self.driver.abort_receive(&self.name)?;
+        self.refresh();
+
+        Ok(())
+    }
+
+    /// Get the replication of `original` contained in this filesystem.
+    /// The replication is named with the mapping rules, and it may not exist yet.
+    pub fn replica(&self, original: &Filesystem) -> Filesystem {
+        let name = Mapping::global().target(&self.name, &original.name);
+
+        Self::new(&name, self.driver)
+    }
+
+    /// Confirm this filesystem is a parent created by `create_parent` to receive the child filesystems.
+    /// The parent has no snapshot and is never mounted, the whole stream can be received over it.
+    fn is_placeholder(&self) -> bool {
+        let mut attribute = FilesystemController::of(self.driver);
+        attribute.refresh_filesystems();
+        if !attribute.exist(&self.name) {
+            return false;
+        }
+
+        let snapshots = self.driver.get_snapshots_of(&self.name);
+        let canmount = self.driver.get_property(&self.name, "canmount");
+        is_placeholder(&snapshots, &canmount)
+    }
+
+    /// Create the missing parent filesystems of this filesystem.
+    /// The parents are created unmountable not to mount over the other filesystems.
+    fn create_parent(&self) -> Result<(), String> {
+        let parent = match self.name.rsplit_once('/') {
+            Some((parent, _)) => parent,
+            None => return Ok(()),
+        };
+
+        let mut attribute = FilesystemController::of(self.driver);
+        if attribute.exist(parent) {
+            return Ok(());
+        }
+
+        elephant_log::info!("create the parent filesystem: {parent}");
+        self.driver.create_filesystem(parent)?;
+        attribute.refresh_filesystems();
+
+        Ok(())
+    }
+
 }
 
 #[derive(Debug, Clone)]
 pub struct SnapshotCollector {
     filesystem: String,
+    driver: &'static Driver,
     snapshots: Vec<Snapshot>,
 }
 
 impl SnapshotCollector {
-    pub fn new(filesystem: &str) -> SnapshotCollector {
-        let attribute = FilesystemController::global();
+    pub fn new(filesystem: &str, driver: &'static Driver) -> SnapshotCollector {
+        let attribute = FilesystemController::of(driver);
         let snapshot_names = attribute.get_snapshots(filesystem);
 
+        // only the snapshots taken by Elephant Archive are collected.
         let iter = snapshot_names.iter();
-        let snapshots: Vec<Snapshot> = iter.map(|s| Snapshot::new(s)).collect();
+        let snapshots: Vec<Snapshot> = iter
+                .filter(|s| Snapshot::is_elephant(s))
+                .map(|s| Snapshot::new(s)).collect();
 
         SnapshotCollector {
             filesystem: filesystem.to_string(),
+            driver,
             snapshots,
         }
     }
@@ -165,12 +1136,36 @@ impl SnapshotCollector {
         self.snapshots.clone()
     }
 
-    pub fn take(&mut self) -> Snapshot {
-        let driver = Driver::get_instance();
+    /// Find the newest snapshot having the same short name as one of `others`.
+    /// The found snapshot is the base of an incremental stream from this filesystem.
+    /// # Arguments
+    /// - `others` - An array of snapshot instance on another filesystem.
+    pub fn newest_common(&self, others: &[Snapshot]) -> Option<Snapshot> {
+        let short_names: Vec<String> = others.iter().map(|s| s.short_name()).collect();
+
+        self.snapshots.iter().rev()
+                .find(|s| short_names.contains(&s.short_name()))
+                .cloned()
+    }
+
+    /// Get the snapshots taken after `base`.
+    /// The snapshots after a bookmark or a destroyed snapshot are found with the time taken.
+    pub fn newer_than(&self, base: &Snapshot) -> Vec<Snapshot> {
+        let position = self.snapshots.iter().position(|s| s == base);
+        match position {
+            Some(index) => self.snapshots[index + 1..].to_vec(),
+            None => {
+                let datetime = base.datetime();
+                self.snapshots.iter().filter(|s| s.datetime() > datetime).cloned().collect()
+            },
+        }
+    }
+
+    pub fn take(&mut self, recursive: bool) -> Snapshot {
         let filesystem = Snapshot::generate_name(&self.filesystem);
-        driver.take_snapshot(&filesystem);
+        self.driver.take_snapshot(&filesystem, recursive);
 
-        let mut attribute = FilesystemController::global();
+        let mut attribute = FilesystemController::of(self.driver);
         attribute.add_snapshot(&filesystem);
 
         let snapshot = Snapshot::new(&filesystem);
@@ -179,36 +1174,94 @@ impl SnapshotCollector {
         snapshot
     }
 
-    /// Purge snapshots.
-    pub fn purge(&self) -> Vec<Snapshot> {
+    /// Purge the snapshots over `retention`.
+    /// The snapshots in `protects` are never destroyed.
+    /// # Arguments
+    /// - `retention` - The retention policy deciding the snapshots kept.
+    /// - `protects` - An array of snapshot instance that must be kept, like the base of the next incremental stream.
+    pub fn purge(&self, retention: &Retention, protects: &[Snapshot]) -> Vec<Snapshot> {
         elephant_log::trace!("purge start");
 
-        let generation = Snapshot::generation(&self.snapshots);
+        let generation = Snapshot::generation(&self.snapshots, retention);
+        let mut destroys = Self::select_purge(&generation, retention);
+
+        destroys.retain(|s| {
+            let protected = protects.iter().any(|p| p.short_name() == s.short_name());
+            if protected {
+                elephant_log::info!("keep the protected snapshot: {}", s.name());
+            }
+            !protected
+        });
+
+        // the held snapshots cannot be destroyed until the holds are released.
+        let names: Vec<String> = destroys.iter().map(|s| s.name()).collect();
+        let holds = self.driver.holds(&names);
+        destroys.retain(|s| {
+            let tags: Vec<&String> = holds.iter()
+                    .filter(|(held, _)| *held == s.name())
+                    .map(|(_, tag)| tag).collect();
+            if !tags.is_empty() {
+                elephant_log::display!("Skip the held snapshot: {} with {:?}", s.name(), tags);
+            }
+            tags.is_empty()
+        });
+
+        // the snapshots with the dependent clones cannot be destroyed until the clones are destroyed.
+        destroys.retain(|s| {
+            let clones = self.driver.get_clones(&s.name());
+            if !clones.is_empty() {
+                elephant_log::display!("Skip the cloned snapshot: {} with {:?}", s.name(), clones);
+            }
+            clones.is_empty()
+        });
+
+        for destroy in destroys.iter() {
+            self.driver.destroy_snapshot(&destroy.name());
+        }
+
+        let mut attribute = FilesystemController::of(self.driver);
+        attribute.destroy_snapshots(&destroys);
+
+        elephant_log::trace!("purge end: {:?}", destroys);
+        destroys
+    }
+
+    /// Select the snapshots purged from `generation` with `retention`.
+    /// A snapshot a day is kept in the middle generation and a snapshot a week in the old generation,
+    /// and then the weekly snapshots over `keep_weeks` are thinned to the monthly snapshots.
+    fn select_purge(generation: &Generation, retention: &Retention) -> Vec<Snapshot> {
         let mut destroys: Vec<Snapshot> = Vec::new();
 
         let offset = Duration::days(1);
         let mut middles = 
-                self.find_frequent_snapshot(&generation.middle, offset);
+                Self::find_frequent_snapshot(&generation.middle, offset);
         destroys.append(&mut middles);
 
         let offset = Duration::weeks(1);
         let mut olds = 
-                self.find_frequent_snapshot(&generation.old, offset);
+                Self::find_frequent_snapshot(&generation.old, offset);
         destroys.append(&mut olds);
 
-        let mut oldests = self.find_oldest_snapshot(
-                &generation.old,
-                configure::SNAPSHOT_KEEP_WEEKS);
-        destroys.append(&mut oldests);
+        let weeklies: Vec<Snapshot> = generation.old.iter()
+                .filter(|s| !destroys.contains(s))
+                .cloned().collect();
+        let mut oldests = Self::find_oldest_snapshot(&weeklies, retention.keep_weeks);
 
-        for destroy in destroys.iter() {
-            Driver.destroy_snapshot(&destroy.name());
-        }
+        if retention.keep_months > 0 {
+            // the weekly snapshots expired are thinned to the monthly snapshots.
+            let offset = Duration::days(30);
+            let mut expires = Self::find_frequent_snapshot(&oldests, offset);
+            let monthlies: Vec<Snapshot> = oldests.iter()
+                    .filter(|s| !expires.contains(s))
+                    .cloned().collect();
+            let mut oldest_monthlies = Self::find_oldest_snapshot(&monthlies, retention.keep_months);
 
-        let mut attribute = FilesystemController::global();
-        attribute.destroy_snapshots(&destroys);
+            oldests = Vec::new();
+            oldests.append(&mut expires);
+            oldests.append(&mut oldest_monthlies);
+        }
+        destroys.append(&mut oldests);
 
-        elephant_log::trace!("purge end: {:?}", destroys);
         destroys
     }
 
@@ -217,10 +1270,10 @@ impl SnapshotCollector {
     /// # Arguments
     /// - `snapshots` - An array of snapshot instance that will be destroyed.
     /// - `interval` - An interval time to keep `snapshots`.
-    fn find_frequent_snapshot(&self, snapshots: &Vec<Snapshot>, interval: Duration) -> Vec<Snapshot> {
+    fn find_frequent_snapshot(snapshots: &[Snapshot], interval: Duration) -> Vec<Snapshot> {
         elephant_log::trace!("start find frequent snapshot");
 
-        let mut base = Local.timestamp_millis(0);
+        let mut base = Local.timestamp_millis_opt(0).unwrap();
 
         let mut destroys = Vec::new();
         for snapshot in snapshots.iter() {
@@ -243,7 +1296,7 @@ impl SnapshotCollector {
     /// Return an array of snapshot instance destroyed.
     /// # Arguments
     /// - `snapshots` - An array of snapshot instance that will be destroyed.
-    fn find_oldest_snapshot(&self, snapshots: &Vec<Snapshot>, number: i32) -> Vec<Snapshot> {
+    fn find_oldest_snapshot(snapshots: &[Snapshot], number: i32) -> Vec<Snapshot> {
         elephant_log::trace!("find oldest snapshot");
 
         let mut destroys: Vec<Snapshot> = Vec::new();
@@ -265,3 +1318,82 @@ impl SnapshotCollector {
         destroys
     }
 }
+
+/// Confirm a filesystem having `snapshots` and the `canmount` property is a parent created to receive its children.
+fn is_placeholder(snapshots: &[String], canmount: &str) -> bool {
+    snapshots.is_empty() && canmount == "off"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    static LOGGER: std::sync::Once = std::sync::Once::new();
+
+    fn snapshots(datetimes: &[NaiveDateTime]) -> Vec<Snapshot> {
+        datetimes.iter()
+                .map(|d| Snapshot::new(&d.format("tank@elephant-%Y-%m%d-%H%M%S").to_string()))
+                .collect()
+    }
+
+    fn retention(keep_weeks: i32, keep_months: i32) -> Retention {
+        Retention { keep_hours: 24, keep_days: 30, keep_weeks, keep_months }
+    }
+
+    fn old(snapshots: &[Snapshot]) -> Generation {
+        // the purge logs the snapshots selected.
+        LOGGER.call_once(|| elephant_log::Logger::init(elephant_log::Level::None));
+        Generation { young: Vec::new(), middle: Vec::new(), old: snapshots.to_vec() }
+    }
+
+    fn kept(snapshots: &[Snapshot], destroys: &[Snapshot]) -> Vec<Snapshot> {
+        snapshots.iter().filter(|s| !destroys.contains(s)).cloned().collect()
+    }
+
+    fn date(day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2022, 1, 1).unwrap().and_hms_opt(12, 0, 0).unwrap()
+                + Duration::days(day as i64 - 1)
+    }
+
+    #[test]
+    fn purge_daily_snapshots_to_weeklies() {
+        // the daily snapshots for 3 weeks, a snapshot a week is kept on the 1st, the 8th, and the 15th.
+        let dailies = snapshots(&(1..=21).map(date).collect::<Vec<_>>());
+        let destroys = SnapshotCollector::select_purge(&old(&dailies), &retention(2, 0));
+
+        // the oldest weekly snapshot is purged, not the newest daily snapshots.
+        assert_eq!(kept(&dailies, &destroys), snapshots(&[date(8), date(15)]));
+    }
+
+    #[test]
+    fn purge_weekly_snapshots_to_monthlies() {
+        let weeklies = snapshots(&(0..10).map(|w| date(1 + w * 7)).collect::<Vec<_>>());
+
+        // the 8 weekly snapshots over 2 are thinned to the monthly snapshots on Jan 1 and Feb 5.
+        let destroys = SnapshotCollector::select_purge(&old(&weeklies), &retention(2, 2));
+        assert_eq!(kept(&weeklies, &destroys), snapshots(&[date(1), date(36), date(57), date(64)]));
+
+        // the oldest monthly snapshot over 1 is purged.
+        let destroys = SnapshotCollector::select_purge(&old(&weeklies), &retention(2, 1));
+        assert_eq!(kept(&weeklies, &destroys), snapshots(&[date(36), date(57), date(64)]));
+
+        // the weekly snapshots over 2 are purged without the monthly snapshots.
+        let destroys = SnapshotCollector::select_purge(&old(&weeklies), &retention(2, 0));
+        assert_eq!(kept(&weeklies, &destroys), snapshots(&[date(57), date(64)]));
+    }
+
+    #[test]
+    fn overwrite_parent_created_for_children() {
+        // 'tank/home' is archived before 'tank', the replica of 'tank' was created for it.
+        assert!(is_placeholder(&[], "off"));
+    }
+
+    #[test]
+    fn keep_filesystem_not_created_for_children() {
+        let snapshots = vec!["archive/tank@elephant-2022-1109-120000".to_string()];
+        assert!(!is_placeholder(&snapshots, "off"));
+        assert!(!is_placeholder(&[], "on"));
+        assert!(!is_placeholder(&[], "noauto"));
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright (c) 2022 Patineboot.
+// All rights reserved.
+// Elephant Archive is licensed under BSD 2-Clause License.
+
+use once_cell::sync::OnceCell;
+
+/// A rule mapping the name of an original filesystem to the name of its replication.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    /// Keep the full name of the original under the archive,
+    /// `tank/home/alice` is archived to `archive_pool/tank/home/alice`.
+    Full,
+    /// Strip the prefix from the name of the original under the archive,
+    /// `tank/home/alice` is archived to `archive_pool/alice` with the `tank/home` prefix.
+    Strip(String),
+    /// Archive the original to the explicit target filesystem,
+    /// `tank/home/alice` is archived to `backup/alice` with `tank/home/alice=backup/alice`.
+    Explicit(String, String),
+}
+
+/// The rules mapping the names of original filesystems to the names of their replications.
+/// The explicit rules win, and then the first other rule maps the name.
+/// The replication is named with the last component of the original without any rule.
+#[derive(Debug, Clone, Default)]
+pub struct Mapping {
+    rules: Vec<Rule>,
+}
+
+static MAPPING_INSTANCE: OnceCell<Mapping> = OnceCell::new();
+
+impl Mapping {
+    /// Make the mapping from the rules like `full`, `strip:tank/home`, or `tank/home/alice=backup/alice`.
+    pub fn parse(rules: &[String]) -> Result<Mapping, String> {
+        let mut parsed = Vec::new();
+        for rule in rules {
+            let rule = if rule == "full" {
+                Rule::Full
+            }
+            else if let Some(prefix) = rule.strip_prefix("strip:").filter(|p| !p.is_empty()) {
+                Rule::Strip(prefix.trim_end_matches('/').to_string())
+            }
+            else {
+                match rule.split_once('=') {
+                    Some((source, target)) if !source.is_empty() && !target.is_empty() => {
+                        Rule::Explicit(source.to_string(), target.to_string())
+                    },
+                    _ => return Err(format!("'{rule}' is not a mapping rule like full, strip:PREFIX, or SOURCE=TARGET")),
+                }
+            };
+            parsed.push(rule);
+        }
+
+        Ok(Mapping { rules: parsed })
+    }
+
+    /// Initialize the mapping used to name all of the replications.
+    pub fn init(mapping: Mapping) {
+        MAPPING_INSTANCE.set(mapping).expect("the mapping is initialized twice");
+    }
+
+    /// Get the mapping used to name all of the replications.
+    pub fn global() -> &'static Mapping {
+        MAPPING_INSTANCE.get_or_init(Mapping::default)
+    }
+
+    /// Get the name of the replication of the `original` filesystem archived to `archive`.
+    pub fn target(&self, archive: &str, original: &str) -> String {
+        let explicit = self.rules.iter().find_map(|rule| match rule {
+            Rule::Explicit(source, target) if source == original => Some(target.clone()),
+            _ => None,
+        });
+        if let Some(target) = explicit {
+            return target;
+        }
+
+        let last = original.rsplit('/').next().unwrap();
+        let rule = self.rules.iter().find(|r| !matches!(r, Rule::Explicit(..)));
+        let relative = match rule {
+            Some(Rule::Full) => original,
+            Some(Rule::Strip(prefix)) => match original.strip_prefix(&format!("{prefix}/")) {
+                Some(stripped) => stripped,
+                // the original out of the prefix keeps its full name.
+                None if original != prefix => original,
+                None => last,
+            },
+            _ => last,
+        };
+
+        format!("{archive}/{relative}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(rules: &[&str]) -> Mapping {
+        let rules: Vec<String> = rules.iter().map(|r| r.to_string()).collect();
+        Mapping::parse(&rules).unwrap()
+    }
+
+    #[test]
+    fn target_without_rules() {
+        assert_eq!(Mapping::default().target("archive_pool", "tank/home/alice"), "archive_pool/alice");
+        assert_eq!(Mapping::default().target("archive_pool", "tank"), "archive_pool/tank");
+    }
+
+    #[test]
+    fn target_full() {
+        assert_eq!(mapping(&["full"]).target("archive_pool", "tank/home/alice"), "archive_pool/tank/home/alice");
+    }
+
+    #[test]
+    fn target_strip() {
+        let mapping = mapping(&["strip:tank/home/"]);
+
+        assert_eq!(mapping.target("archive_pool", "tank/home/alice/docs"), "archive_pool/alice/docs");
+        // the prefix itself is named with its last component.
+        assert_eq!(mapping.target("archive_pool", "tank/home"), "archive_pool/home");
+        // a filesystem out of the prefix or only sharing its characters keeps its full name.
+        assert_eq!(mapping.target("archive_pool", "tank/var/log"), "archive_pool/tank/var/log");
+        assert_eq!(mapping.target("archive_pool", "tank/homes/bob"), "archive_pool/tank/homes/bob");
+    }
+
+    #[test]
+    fn target_explicit_wins() {
+        let mapping = mapping(&["full", "tank/home/alice=backup/alice"]);
+
+        assert_eq!(mapping.target("archive_pool", "tank/home/alice"), "backup/alice");
+        assert_eq!(mapping.target("archive_pool", "tank/home/bob"), "archive_pool/tank/home/bob");
+    }
+
+    #[test]
+    fn parse_malformed_rules() {
+        for rule in ["strip:", "=backup/alice", "tank/home/alice=", "tank/home/alice"] {
+            assert!(Mapping::parse(&[rule.to_string()]).is_err(), "'{rule}' is parsed");
+        }
+    }
+}
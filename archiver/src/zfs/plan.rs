@@ -0,0 +1,51 @@
+// Copyright (c) 2022 Patineboot.
+// All rights reserved.
+// Elephant Archive is licensed under BSD 2-Clause License.
+
+use super::relay::format_bytes;
+
+/// A plan archiving a ZFS filesystem, shown instead of archiving it on a dry run.
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    /// The name of the original filesystem.
+    pub source: String,
+    /// The name of the replication or the directory of the stream store.
+    pub target: String,
+    /// How the streams are sent like `full` or `incremental from ...`.
+    pub kind: String,
+    /// The snapshots sent with their estimated bytes in the order they are sent.
+    pub streams: Vec<(String, u64)>,
+    /// The bytes available on the target, or `None` if unknown.
+    pub available: Option<u64>,
+    /// The notes to be confirmed before archiving like a divergence of the replication.
+    pub notes: Vec<String>,
+}
+
+impl Plan {
+    /// Get the total of the estimated bytes.
+    pub fn total(&self) -> u64 {
+        self.streams.iter().map(|(_, bytes)| bytes).sum()
+    }
+
+    /// Show this plan on the display.
+    pub fn show(&self) {
+        elephant_log::display!("Plan: {} -> {} ({})", self.source, self.target, self.kind);
+        for (snapshot, bytes) in self.streams.iter() {
+            elephant_log::display!("  send {snapshot}: {}", format_bytes(*bytes));
+        }
+
+        let total = self.total();
+        match self.available {
+            Some(available) => {
+                let enough = if total <= available { "enough" } else { "NOT enough" };
+                elephant_log::display!("  total {} of {} available: {enough}",
+                        format_bytes(total), format_bytes(available));
+            },
+            None => elephant_log::display!("  total {}, the available space is unknown", format_bytes(total)),
+        }
+
+        for note in self.notes.iter() {
+            elephant_log::display!("  note: {note}");
+        }
+    }
+}
@@ -0,0 +1,381 @@
+// Copyright (c) 2022 Patineboot.
+// All rights reserved.
+// Elephant Archive is licensed under BSD 2-Clause License.
+
+use std::io::{self, Read, Write, ErrorKind, IsTerminal};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use sha2::{Sha256, Digest};
+
+use crate::configure;
+
+/// The size of the buffer relaying a stream.
+const BUFFER_SIZE: usize = 128 * 1024;
+
+/// The result of a stream relayed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    /// The number of bytes relayed.
+    pub bytes: u64,
+    /// The SHA-256 checksum of the bytes relayed in lowercase hexadecimal.
+    pub sha256: String,
+}
+
+/// Format `bytes` in a human-readable unit like `1.5 GiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Parse a size like `512K`, `10M`, or `1G` into bytes, the units are powers of 1024.
+pub fn parse_bytes(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let (number, unit) = match size.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => size.split_at(index),
+        None => (size, ""),
+    };
+
+    let multiplier: u64 = match unit.to_ascii_uppercase().trim_end_matches(['B', 'I']) {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("'{size}' is not a size.")),
+    };
+
+    let number: u64 = number.parse().map_err(|_| format!("'{size}' is not a size."))?;
+    number.checked_mul(multiplier).ok_or_else(|| format!("'{size}' is too large."))
+}
+
+/// Format `duration` as `HH:MM:SS`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// A progress of a stream showing the bytes transferred, the throughput, and the ETA.
+/// The progress is redrawn on a line of a terminal,
+/// or logged at intervals on a non-terminal output or with the other progresses in parallel.
+pub struct Progress {
+    label: String,
+    estimate: Option<u64>,
+    terminal: bool,
+    started: Instant,
+    shown: Instant,
+}
+
+impl Progress {
+    /// Make a progress of the `label` stream estimated at `estimate` bytes.
+    /// The progress is logged in lines if the other streams are sent in `parallel`.
+    pub fn new(label: &str, estimate: Option<u64>, parallel: bool) -> Progress {
+        let now = Instant::now();
+
+        Progress {
+            label: label.to_string(),
+            estimate,
+            terminal: !parallel && io::stderr().is_terminal(),
+            started: now,
+            shown: now,
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        if self.terminal {
+            Duration::from_secs(1)
+        } else {
+            Duration::from_secs(configure::PROGRESS_LOG_SECONDS)
+        }
+    }
+
+    /// Show the progress with `bytes` transferred if the interval has passed.
+    fn update(&mut self, bytes: u64) {
+        if self.shown.elapsed() < self.interval() {
+            return;
+        }
+
+        self.shown = Instant::now();
+        self.show(bytes, false);
+    }
+
+    /// Show the progress with `bytes` transferred at the end of the stream.
+    fn done(&mut self, bytes: u64) {
+        self.show(bytes, true);
+    }
+
+    fn show(&self, bytes: u64, done: bool) {
+        let elapsed = self.started.elapsed();
+        let rate = bytes as f64 / elapsed.as_secs_f64().max(0.001);
+
+        let amount = match self.estimate {
+            Some(estimate) if estimate > 0 => format!("{} / {} ({}%)",
+                    format_bytes(bytes), format_bytes(estimate),
+                    (bytes as f64 * 100.0 / estimate as f64).min(100.0) as u64),
+            _ => format_bytes(bytes),
+        };
+        let eta = match self.estimate {
+            _ if done => format!("in {}", format_duration(elapsed)),
+            Some(estimate) if rate > 0.0 => {
+                let remaining = estimate.saturating_sub(bytes) as f64 / rate;
+                format!("ETA {}", format_duration(Duration::from_secs_f64(remaining)))
+            },
+            _ => "ETA --:--:--".to_string(),
+        };
+
+        let line = format!("{}: {amount}, {}/s, {eta}", self.label, format_bytes(rate as u64));
+        if self.terminal {
+            let end = if done { "\n" } else { "" };
+            eprint!("\r\x1b[2K{line}{end}");
+        } else {
+            elephant_log::display!("{line}");
+        }
+    }
+}
+
+/// A relay copying a stream from a reader to a writer through this process.
+/// The relay counts the bytes and computes the checksum while the stream passes through.
+/// The relay can limit the rate of the stream, and can buffer the stream like `mbuffer`
+/// to keep the reader running while the writer is busy.
+#[derive(Default)]
+pub struct Relay {
+    hasher: Sha256,
+    bytes: u64,
+    progress: Option<Progress>,
+    rate_limit: Option<u64>,
+    buffer: Option<u64>,
+    started: Option<Instant>,
+}
+
+impl Relay {
+    pub fn new() -> Relay {
+        Relay::default()
+    }
+
+    /// Make a relay with the options.
+    /// # Arguments
+    /// - `progress` - The progress shown while the stream passes through.
+    /// - `rate_limit` - The maximum bytes per second of the stream.
+    /// - `buffer` - The bytes of the buffer between the reader and the writer.
+    pub fn with(progress: Option<Progress>, rate_limit: Option<u64>, buffer: Option<u64>) -> Relay {
+        Relay {
+            progress,
+            rate_limit,
+            buffer,
+            ..Relay::default()
+        }
+    }
+
+    /// Copy the whole of `reader` to `writer`.
+    pub fn copy<R: Read + Send>(&mut self, reader: &mut R, writer: &mut dyn Write) -> io::Result<u64> {
+        self.started = Some(Instant::now());
+
+        match self.buffer {
+            Some(size) => self.copy_buffered(reader, writer, size)?,
+            None => {
+                let mut block = vec![0u8; BUFFER_SIZE];
+                loop {
+                    let length = read_block(reader, &mut block)?;
+                    if length == 0 {
+                        break;
+                    }
+                    self.pass(&block[..length], writer)?;
+                }
+            },
+        }
+        writer.flush()?;
+
+        if let Some(progress) = self.progress.as_mut() {
+            progress.done(self.bytes);
+        }
+
+        Ok(self.bytes)
+    }
+
+    /// Copy the whole of `reader` to `writer` through a ring of blocks holding `size` bytes.
+    /// The reader runs on another thread and fills the ring while the writer drains it.
+    fn copy_buffered<R: Read + Send>(&mut self, reader: &mut R, writer: &mut dyn Write,
+            size: u64) -> io::Result<()> {
+        let blocks = (size as usize / BUFFER_SIZE).max(1);
+        let (sender, receiver) = mpsc::sync_channel::<io::Result<Vec<u8>>>(blocks);
+
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                loop {
+                    let mut block = vec![0u8; BUFFER_SIZE];
+                    let read = read_block(reader, &mut block);
+                    if matches!(read, Ok(0)) {
+                        break;
+                    }
+
+                    // an error is passed to the writer to fail the copy of the truncated stream.
+                    let failed = read.is_err();
+                    let read = read.map(|length| {
+                        block.truncate(length);
+                        block
+                    });
+
+                    // the writer has stopped if the ring is disconnected.
+                    if sender.send(read).is_err() || failed {
+                        break;
+                    }
+                }
+            });
+
+            // `receiver` is dropped on an error to stop the reader.
+            self.drain(receiver, writer)
+        })
+    }
+
+    /// Write the blocks on the ring to `writer` until the reader stops.
+    fn drain(&mut self, receiver: mpsc::Receiver<io::Result<Vec<u8>>>, writer: &mut dyn Write) -> io::Result<()> {
+        for block in receiver {
+            self.pass(&block?, writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pass `block` through to `writer`, waiting to keep the rate limit.
+    fn pass(&mut self, block: &[u8], writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(block)?;
+        self.hasher.update(block);
+        self.bytes += block.len() as u64;
+
+        if let Some(progress) = self.progress.as_mut() {
+            progress.update(self.bytes);
+        }
+
+        if let (Some(rate_limit), Some(started)) = (self.rate_limit, self.started) {
+            let expected = Duration::from_secs_f64(self.bytes as f64 / rate_limit as f64);
+            let elapsed = started.elapsed();
+            if expected > elapsed {
+                thread::sleep(expected - elapsed);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finish the relay and get the result.
+    pub fn finish(self) -> Transfer {
+        let digest = self.hasher.finalize();
+        let sha256 = digest.iter().map(|b| format!("{b:02x}")).collect();
+
+        Transfer {
+            bytes: self.bytes,
+            sha256,
+        }
+    }
+
+    /// Compute the SHA-256 checksum of the whole of `reader`.
+    pub fn checksum<R: Read + Send>(reader: &mut R) -> io::Result<Transfer> {
+        let mut relay = Relay::new();
+        relay.copy(reader, &mut io::sink())?;
+
+        Ok(relay.finish())
+    }
+}
+
+/// Read a block from `reader` retrying on an interruption.
+/// Return 0 at the end of `reader`.
+fn read_block<R: Read>(reader: &mut R, block: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match reader.read(block) {
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reader yielding `blocks` blocks of zeros and failing after them.
+    struct FailingReader {
+        blocks: usize,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.blocks == 0 {
+                return Err(io::Error::other("device error"));
+            }
+            self.blocks -= 1;
+            buffer.fill(0);
+            Ok(buffer.len())
+        }
+    }
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_bytes("512"), Ok(512));
+        assert_eq!(parse_bytes("512K"), Ok(512 * 1024));
+        assert_eq!(parse_bytes("10m"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_bytes(" 1G "), Ok(1 << 30));
+        assert_eq!(parse_bytes("2TiB"), Ok(2 << 40));
+        assert_eq!(parse_bytes("64KB"), Ok(64 * 1024));
+        assert_eq!(parse_bytes("100B"), Ok(100));
+    }
+
+    #[test]
+    fn parse_malformed_sizes() {
+        for size in ["", "K", "1.5G", "10X", "-1M", "1 G"] {
+            assert!(parse_bytes(size).is_err(), "'{size}' is parsed");
+        }
+    }
+
+    #[test]
+    fn parse_too_large_sizes() {
+        assert_eq!(parse_bytes("16777215T"), Ok(16777215 << 40));
+        for size in ["16777216T", "18446744073709551615K"] {
+            assert!(parse_bytes(size).is_err(), "'{size}' is parsed");
+        }
+    }
+
+    #[test]
+    fn format_sizes() {
+        assert_eq!(format_bytes(1000), "1000 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 << 30), "3.0 GiB");
+    }
+
+    #[test]
+    fn copy_buffered_fails_on_read_error() {
+        let mut relay = Relay::with(None, None, Some(4 * BUFFER_SIZE as u64));
+        let result = relay.copy(&mut FailingReader { blocks: 3 }, &mut io::sink());
+
+        assert_eq!(result.unwrap_err().to_string(), "device error");
+    }
+
+    #[test]
+    fn copy_fails_on_read_error() {
+        let mut relay = Relay::new();
+        let result = relay.copy(&mut FailingReader { blocks: 3 }, &mut io::sink());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn copy_buffered_relays_whole_stream() {
+        let stream = vec![7u8; 3 * BUFFER_SIZE + 100];
+        let mut relay = Relay::with(None, None, Some(2 * BUFFER_SIZE as u64));
+        let mut copied = Vec::new();
+
+        assert_eq!(relay.copy(&mut stream.as_slice(), &mut copied).unwrap(), stream.len() as u64);
+        assert_eq!(copied, stream);
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright (c) 2022 Patineboot.
+// All rights reserved.
+// Elephant Archive is licensed under BSD 2-Clause License.
+
+use chrono::{Local, DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+
+use super::Snapshot;
+
+/// A selector choosing a snapshot of a filesystem by its short name or a point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    /// The newest snapshot.
+    Latest,
+    /// The oldest snapshot.
+    Oldest,
+    /// The snapshot with the short name.
+    Name(String),
+    /// The snapshot taken nearest to the point in time.
+    At(DateTime<Local>),
+    /// The newest snapshot taken at or before the point in time.
+    Before(DateTime<Local>),
+    /// The oldest snapshot taken at or after the point in time.
+    After(DateTime<Local>),
+}
+
+impl Selector {
+    /// Make the selector from an expression like `latest`, `oldest`, a short name of a snapshot,
+    /// a point in time like `2022-11-03 09:15`, `2 days ago`, `yesterday 18:00`, `last tuesday 09:00`,
+    /// or a point in time bounded with `before` or `after` like `before 2022-11-03`.
+    pub fn parse(expression: &str) -> Result<Selector, String> {
+        let expression = expression.trim();
+        let lower = expression.to_lowercase();
+
+        let selector = match lower.as_str() {
+            "latest" | "newest" => Selector::Latest,
+            "oldest" => Selector::Oldest,
+            _ => if let Some(time) = lower.strip_prefix("before ") {
+                Selector::Before(Self::parse_datetime(time)?)
+            }
+            else if let Some(time) = lower.strip_prefix("after ") {
+                Selector::After(Self::parse_datetime(time)?)
+            }
+            else if lower.ends_with(" ago") {
+                Selector::At(Self::parse_datetime(&lower)?)
+            }
+            else if let Some(datetime) = Self::datetime_of(&lower) {
+                Selector::At(datetime)
+            }
+            else {
+                // not a point in time, the short name of a snapshot.
+                Selector::Name(expression.to_string())
+            },
+        };
+
+        Ok(selector)
+    }
+
+    /// Select the snapshot from `snapshots` in the order of creation.
+    /// The points in time are compared with the snapshots taken by Elephant Archive.
+    pub fn select<'a>(&self, snapshots: &'a [Snapshot]) -> Option<&'a Snapshot> {
+        let dated = snapshots.iter()
+                .filter(|s| Snapshot::is_elephant(&s.name()))
+                .map(|s| (s, s.datetime()));
+
+        let selected = match self {
+            Selector::Latest => return snapshots.last(),
+            Selector::Oldest => return snapshots.first(),
+            Selector::Name(short_name) => return snapshots.iter().find(|s| s.short_name() == *short_name),
+            Selector::At(time) => dated.min_by_key(|(_, datetime)| (*datetime - *time).abs()),
+            Selector::Before(time) => dated.filter(|(_, datetime)| datetime <= time).max_by_key(|(_, datetime)| *datetime),
+            Selector::After(time) => dated.filter(|(_, datetime)| datetime >= time).min_by_key(|(_, datetime)| *datetime),
+        };
+
+        selected.map(|(snapshot, _)| snapshot)
+    }
+
+    /// Parse `expression` as a point in time, or return an error.
+    fn parse_datetime(expression: &str) -> Result<DateTime<Local>, String> {
+        Self::datetime_of(expression.trim()).ok_or_else(|| format!(
+                "'{expression}' is not a point in time like '2022-11-03 09:15', '2 days ago', or 'last tuesday 09:00'"))
+    }
+
+    /// Get the point in time of the lower case `expression`, or `None` if it is not a point in time.
+    fn datetime_of(expression: &str) -> Option<DateTime<Local>> {
+        let now = Local::now();
+
+        if expression == "now" {
+            return Some(now);
+        }
+
+        // an absolute timestamp.
+        for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+            if let Ok(datetime) = NaiveDateTime::parse_from_str(expression, format) {
+                return Self::local(datetime);
+            }
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(expression, "%Y-%m-%d") {
+            return Self::local(date.and_time(NaiveTime::MIN));
+        }
+
+        // a relative expression like `2 days ago`.
+        if let Some(amount) = expression.strip_suffix(" ago") {
+            let (count, unit) = amount.split_once(' ')?;
+            let count: u32 = count.parse().ok()?;
+            return match unit.trim_end_matches('s') {
+                "minute" => Some(now - Duration::minutes(count as i64)),
+                "hour" => Some(now - Duration::hours(count as i64)),
+                "day" => Some(now - Duration::days(count as i64)),
+                "week" => Some(now - Duration::weeks(count as i64)),
+                "month" => now.checked_sub_months(Months::new(count)),
+                "year" => now.checked_sub_months(Months::new(count * 12)),
+                _ => None,
+            };
+        }
+
+        // a day with an optional time of the day like `yesterday 18:00` or `last tuesday 09:00`.
+        let (day, time) = match expression.rsplit_once(' ') {
+            Some((day, time)) if time.contains(':') => (day, Self::time_of(time)?),
+            _ => (expression, NaiveTime::MIN),
+        };
+        let today = now.date_naive();
+        let date = match day {
+            "today" => today,
+            "yesterday" => today.pred_opt()?,
+            _ => {
+                let weekday: Weekday = day.strip_prefix("last ")?.parse().ok()?;
+                // the last weekday is before today, a week ago on the same weekday.
+                let days = (today.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday() - 1) % 7 + 1;
+                today - Duration::days(days as i64)
+            },
+        };
+
+        Self::local(date.and_time(time))
+    }
+
+    /// Parse `time` as a time of the day like `09:00` or `09:00:30`.
+    fn time_of(time: &str) -> Option<NaiveTime> {
+        NaiveTime::parse_from_str(time, "%H:%M:%S")
+                .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+                .ok()
+    }
+
+    /// Get the local point in time of `datetime`, the earlier one if it is ambiguous.
+    fn local(datetime: NaiveDateTime) -> Option<DateTime<Local>> {
+        Local.from_local_datetime(&datetime).earliest()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(datetime: &str) -> DateTime<Local> {
+        let datetime = NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M").unwrap();
+        Local.from_local_datetime(&datetime).unwrap()
+    }
+
+    fn snapshots() -> Vec<Snapshot> {
+        ["pool/fs@elephant-2022-1101-100000", "pool/fs@manual", "pool/fs@elephant-2022-1102-100000",
+                "pool/fs@elephant-2022-1103-100000"]
+                .iter().map(|name| Snapshot::new(name)).collect()
+    }
+
+    fn selected(selector: Selector) -> Option<String> {
+        selector.select(&snapshots()).map(|s| s.short_name())
+    }
+
+    #[test]
+    fn parse_keywords_and_names() {
+        assert_eq!(Selector::parse("latest"), Ok(Selector::Latest));
+        assert_eq!(Selector::parse(" Oldest "), Ok(Selector::Oldest));
+        assert_eq!(Selector::parse("manual"), Ok(Selector::Name("manual".to_string())));
+        assert_eq!(Selector::parse("before 2022-11-03"), Ok(Selector::Before(at("2022-11-03 00:00"))));
+        assert_eq!(Selector::parse("after 2022-11-03 09:15"), Ok(Selector::After(at("2022-11-03 09:15"))));
+        assert!(Selector::parse("before someday").is_err());
+    }
+
+    #[test]
+    fn parse_units_ago() {
+        let now = Local::now();
+        let cases = [
+            ("5 minutes ago", now - Duration::minutes(5)),
+            ("1 hour ago", now - Duration::hours(1)),
+            ("2 days ago", now - Duration::days(2)),
+            ("3 weeks ago", now - Duration::weeks(3)),
+            ("1 month ago", now.checked_sub_months(Months::new(1)).unwrap()),
+            ("2 years ago", now.checked_sub_months(Months::new(24)).unwrap()),
+        ];
+
+        for (expression, expected) in cases {
+            let datetime = match Selector::parse(expression) {
+                Ok(Selector::At(datetime)) => datetime,
+                other => panic!("'{expression}' is parsed as {other:?}"),
+            };
+            assert!((datetime - expected).abs() < Duration::seconds(5), "'{expression}' is {datetime}");
+        }
+
+        assert!(Selector::parse("2 fortnights ago").is_err());
+    }
+
+    #[test]
+    fn last_weekday_is_within_the_last_week() {
+        let today = Local::now().date_naive();
+
+        for weekday in ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"] {
+            let datetime = match Selector::parse(&format!("last {weekday} 09:00")) {
+                Ok(Selector::At(datetime)) => datetime,
+                other => panic!("'last {weekday}' is parsed as {other:?}"),
+            };
+
+            let days = (today - datetime.date_naive()).num_days();
+            assert!((1..=7).contains(&days), "'last {weekday}' is {days} days ago");
+            assert_eq!(datetime.weekday(), weekday.parse::<Weekday>().unwrap());
+            assert_eq!(datetime.time(), NaiveTime::from_hms_opt(9, 0, 0).unwrap());
+        }
+
+        // the last weekday of today is a week ago.
+        let weekday = today.weekday().to_string();
+        let datetime = Selector::datetime_of(&format!("last {}", weekday.to_lowercase())).unwrap();
+        assert_eq!(datetime.date_naive(), today - Duration::days(7));
+    }
+
+    #[test]
+    fn select_bounds() {
+        assert_eq!(selected(Selector::Before(at("2022-11-02 10:00"))), Some("elephant-2022-1102-100000".to_string()));
+        assert_eq!(selected(Selector::Before(at("2022-11-02 09:59"))), Some("elephant-2022-1101-100000".to_string()));
+        assert_eq!(selected(Selector::Before(at("2022-11-01 09:59"))), None);
+        assert_eq!(selected(Selector::After(at("2022-11-02 10:00"))), Some("elephant-2022-1102-100000".to_string()));
+        assert_eq!(selected(Selector::After(at("2022-11-02 10:01"))), Some("elephant-2022-1103-100000".to_string()));
+        assert_eq!(selected(Selector::After(at("2022-11-03 10:01"))), None);
+    }
+
+    #[test]
+    fn select_nearest_and_names() {
+        assert_eq!(selected(Selector::At(at("2022-11-02 21:00"))), Some("elephant-2022-1102-100000".to_string()));
+        assert_eq!(selected(Selector::At(at("2022-11-02 23:00"))), Some("elephant-2022-1103-100000".to_string()));
+        assert_eq!(selected(Selector::Name("manual".to_string())), Some("manual".to_string()));
+        assert_eq!(selected(Selector::Latest), Some("elephant-2022-1103-100000".to_string()));
+        assert_eq!(selected(Selector::Oldest), Some("elephant-2022-1101-100000".to_string()));
+    }
+}
@@ -2,10 +2,51 @@
 // All rights reserved.
 // Elephant Archive is licensed under BSD 2-Clause License.
 
-use chrono::{Local, DateTime, Duration};
+use chrono::{Local, DateTime, Duration, NaiveDateTime};
 
 use crate::configure;
 
+/// A retention policy deciding the snapshots kept on a filesystem.
+/// All of the snapshots are kept for `keep_hours` hours, a snapshot a day for `keep_days` days,
+/// the `keep_weeks` snapshots a week, and then the `keep_months` snapshots a month.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Retention {
+    pub keep_hours: i32,
+    pub keep_days: i32,
+    pub keep_weeks: i32,
+    pub keep_months: i32,
+}
+
+impl Retention {
+    /// The retention policy of the original filesystems.
+    pub fn original() -> Retention {
+        Retention {
+            keep_hours: configure::SNAPSHOT_KEEP_HOURS,
+            keep_days: configure::SNAPSHOT_KEEP_DAYS,
+            keep_weeks: configure::SNAPSHOT_KEEP_WEEKS,
+            keep_months: 0,
+        }
+    }
+
+    /// The retention policy of the replications on the archive.
+    /// The `keep-hours`, `keep-days`, `keep-weeks`, and `keep-months` settings of the archive
+    /// override the numbers configured.
+    pub fn archive(setting: impl Fn(&str) -> Option<String>) -> Result<Retention, String> {
+        let keep = |key: &str, default: i32| match setting(key) {
+            Some(value) => value.parse::<i32>().ok().filter(|v| *v >= 0)
+                    .ok_or_else(|| format!("'{value}' is not a number of the '{key}' setting")),
+            None => Ok(default),
+        };
+
+        Ok(Retention {
+            keep_hours: keep("keep-hours", configure::ARCHIVE_KEEP_HOURS)?,
+            keep_days: keep("keep-days", configure::ARCHIVE_KEEP_DAYS)?,
+            keep_weeks: keep("keep-weeks", configure::ARCHIVE_KEEP_WEEKS)?,
+            keep_months: keep("keep-months", configure::ARCHIVE_KEEP_MONTHS)?,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Generation {
     pub young: Vec<Snapshot>,
@@ -38,12 +79,44 @@ impl Snapshot {
         self.name.clone()
     }
 
+    /// Get the short name of the snapshot, the part after '@' of the snapshot name.
+    /// The short name of a bookmark is the part after '#'.
+    pub fn short_name(&self) -> String {
+        let (_, short_name) = self.name.split_once(['@', '#']).unwrap();
+        short_name.to_string()
+    }
+
+    /// Confirm this instance is a bookmark like `pool/fs#name` instead of a snapshot.
+    pub fn is_bookmark(&self) -> bool {
+        self.name.contains('#')
+    }
+
+    /// Get the bookmark marking this snapshot with the same short name.
+    pub fn bookmark(&self) -> Snapshot {
+        Snapshot::new(&self.name.replacen('@', "#", 1))
+    }
+
+    /// Confirm the `name` snapshot is taken by Elephant Archive or not.
+    pub fn is_elephant(name: &str) -> bool {
+        let prefix = configure::SNAPSHOT_PREFIX;
+
+        let short_name = match name.split_once(['@', '#']) {
+            Some((_, short_name)) => short_name,
+            None => return false,
+        };
+
+        NaiveDateTime::parse_from_str(
+                short_name,
+                &format!("{prefix}-%Y-%m%d-%H%M%S")
+        ).is_ok()
+    }
+
     pub fn datetime(&self) -> DateTime<Local> {
         let prefix = configure::SNAPSHOT_PREFIX;
 
         let now = Local::now();
 
-        let short_name = self.name.split("@").last().unwrap();
+        let short_name = self.name.split(['@', '#']).next_back().unwrap();
         let short_name_tz = format!("{}{}", short_name, now.offset());
 
         let datetime_offset = DateTime::parse_from_str(
@@ -55,11 +128,11 @@ impl Snapshot {
         datetime_local
     }
 
-    pub fn generation(snapshots: &Vec<Snapshot>) -> Generation {
+    pub fn generation(snapshots: &[Snapshot], retention: &Retention) -> Generation {
         elephant_log::trace!("get generation start");
 
-        let hours_duration = Duration::hours(configure::SNAPSHOT_KEEP_HOURS as i64);
-        let days_duration = Duration::days(configure::SNAPSHOT_KEEP_DAYS  as i64);
+        let hours_duration = Duration::hours(retention.keep_hours as i64);
+        let days_duration = Duration::days(retention.keep_days as i64);
 
         let local: DateTime<Local> = Local::now();
         let hours_limit = local - hours_duration;
@@ -85,3 +158,42 @@ impl Snapshot {
 
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings<'a>(pairs: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |key| pairs.iter().find(|(k, _)| *k == key).map(|(_, v)| v.to_string())
+    }
+
+    #[test]
+    fn archive_retention_configured() {
+        let retention = Retention::archive(settings(&[])).unwrap();
+
+        assert_eq!(retention, Retention {
+            keep_hours: configure::ARCHIVE_KEEP_HOURS,
+            keep_days: configure::ARCHIVE_KEEP_DAYS,
+            keep_weeks: configure::ARCHIVE_KEEP_WEEKS,
+            keep_months: configure::ARCHIVE_KEEP_MONTHS,
+        });
+    }
+
+    #[test]
+    fn archive_retention_overridden() {
+        let retention = Retention::archive(settings(&[("keep-weeks", "8"), ("keep-months", "0")])).unwrap();
+
+        assert_eq!(retention.keep_hours, configure::ARCHIVE_KEEP_HOURS);
+        assert_eq!(retention.keep_days, configure::ARCHIVE_KEEP_DAYS);
+        assert_eq!(retention.keep_weeks, 8);
+        assert_eq!(retention.keep_months, 0);
+    }
+
+    #[test]
+    fn archive_retention_malformed() {
+        for value in ["-1", "ten", "1.5", ""] {
+            let message = Retention::archive(settings(&[("keep-days", value)])).unwrap_err();
+            assert!(message.contains("'keep-days' setting"), "{message}");
+        }
+    }
+}
@@ -0,0 +1,8 @@
+#!/bin/sh
+
+# A stand-in of a remote transport like 'ssh backup-host'.
+# It runs the command lines of the archive on this machine, so the transport
+# can be tried without another host:
+#   elephant-archive archive -a archive_pool -t ./transport-local.sh zfs_pool
+
+exec "$@"